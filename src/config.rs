@@ -0,0 +1,75 @@
+use std::{collections::HashSet, fmt::Display, rc::Rc};
+
+use crate::time::{FixedTimeSource, SystemTimeSource, TimeSource};
+
+/// What a script is allowed to reach outside of the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Filesystem,
+    Clock,
+    Environment,
+    Process,
+    Random,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Filesystem,
+        Capability::Clock,
+        Capability::Environment,
+        Capability::Process,
+        Capability::Random,
+    ];
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::Filesystem => write!(f, "filesystem"),
+            Capability::Clock => write!(f, "clock"),
+            Capability::Environment => write!(f, "environment"),
+            Capability::Process => write!(f, "process"),
+            Capability::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// Settings chosen by whoever runs a script, before it starts.
+#[derive(Clone)]
+pub struct Config {
+    /// Maximum number of bytes the script may allocate, unbounded when `None`.
+    pub memory_limit: Option<usize>,
+    /// Allow-list of the standard library capabilities. Natives needing a
+    /// capability that is not listed raise a runtime error when called.
+    pub capabilities: HashSet<Capability>,
+    /// Time read by `clock`.
+    pub time_source: Rc<dyn TimeSource>,
+    /// Seed of the generator behind `random`, taken from the time when `None`.
+    pub seed: Option<u64>,
+    /// Runs the garbage collector on every allocation, to shake out bugs.
+    pub gc_stress: bool,
+}
+
+impl Config {
+    /// Pins the clock and the random seed so that two runs of the same script
+    /// give the same output.
+    pub fn deterministic(self) -> Config {
+        Config {
+            time_source: Rc::new(FixedTimeSource(0.)),
+            seed: Some(0),
+            ..self
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            memory_limit: None,
+            capabilities: Capability::ALL.into_iter().collect(),
+            time_source: Rc::new(SystemTimeSource),
+            seed: None,
+            gc_stress: false,
+        }
+    }
+}
@@ -1,44 +1,254 @@
 use std::rc::Rc;
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, mem};
 
-use crate::standard::clock;
-use crate::{error::LoxRuntimeException, value::Value};
+use crate::config::{Capability, Config};
+use crate::event_loop::{ChannelState, FutureState};
+use crate::gc::{address, Channel, Future, Generator, HeapStats, List, Locals, Map, Object};
+use crate::generator::Machine;
+use crate::list::ListItems;
+use crate::map::{Key, OrderedMap};
+use crate::runtime::Runtime;
+use crate::standard::{
+    channel, clock, delete, exit, filter, freeze, get_env, has, insert, keys, len, map, next, pop,
+    push, random, read_file, receive, remove, send, sleep, slice, sort, spawn,
+};
+use crate::{
+    error::LoxRuntimeException,
+    grammar::Declaration,
+    value::{Native, Value},
+};
+
+/// A value bound to a name in a scope.
+pub struct Binding {
+    pub value: Value,
+    /// Constants can't be assigned, nor declared again in their scope.
+    pub constant: bool,
+}
 
 #[derive(Clone)]
 pub struct Environment {
     enclosing: Option<Box<Environment>>,
-    locals: Rc<RefCell<HashMap<String, Value>>>,
+    locals: Locals,
+    runtime: Rc<Runtime>,
 }
 
 impl Environment {
-    pub fn new_global() -> Environment {
+    pub fn new_global(config: &Config) -> Environment {
+        Environment::new_root(Rc::new(Runtime::new(config)))
+    }
+
+    /// A global environment for a module, sharing the runtime of this one.
+    pub fn new_module(&self) -> Environment {
+        Environment::new_root(self.runtime.clone())
+    }
+
+    fn new_root(runtime: Rc<Runtime>) -> Environment {
         let global = Environment {
             locals: Rc::new(RefCell::new(HashMap::new())),
             enclosing: None,
+            runtime,
         };
+        global
+            .runtime
+            .heap
+            .track(Object::Scope(global.locals.clone()));
 
-        global.define(
-            "clock".to_owned(),
-            Some(Value::NativeCallable("clock".to_owned(), 0, clock)),
-        );
+        global.define_native("clock", 0, clock);
+        global.define_native("readFile", 1, read_file);
+        global.define_native("getEnv", 1, get_env);
+        global.define_native("exit", 1, exit);
+        global.define_native("random", 0, random);
+        global.define_native("len", 1, len);
+        global.define_native("push", 2, push);
+        global.define_native("pop", 1, pop);
+        global.define_native("insert", 3, insert);
+        global.define_native("remove", 2, remove);
+        global.define_native("slice", 3, slice);
+        global.define_native("map", 2, map);
+        global.define_native("filter", 2, filter);
+        global.define_native("sort", 1, sort);
+        global.define_native("keys", 1, keys);
+        global.define_native("has", 2, has);
+        global.define_native("delete", 2, delete);
+        global.define_native("next", 1, next);
+        global.define_native("freeze", 1, freeze);
 
         global
     }
 
     pub fn new_local(&self) -> Environment {
-        Environment {
+        let local = Environment {
             locals: Rc::new(RefCell::new(HashMap::new())),
             enclosing: Some(Box::new(self.clone())),
+            runtime: self.runtime.clone(),
+        };
+        self.runtime.heap.track(Object::Scope(local.locals.clone()));
+
+        local
+    }
+
+    /// Visits the scopes of this environment, innermost first.
+    pub fn trace(&self, visit: &mut impl FnMut(usize)) {
+        visit(address(&self.locals));
+        if let Some(enclosing) = &self.enclosing {
+            enclosing.trace(visit);
         }
     }
 
+    /// Allocates a list shared by reference.
+    pub fn new_list(&self, items: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+        self.allocate(items.len() * mem::size_of::<Value>())?;
+
+        let list: List = Rc::new(RefCell::new(ListItems::new(items)));
+        self.runtime.heap.track(Object::List(list.clone()));
+
+        Ok(Value::List(list))
+    }
+
+    /// Allocates a map shared by reference.
+    pub fn new_map(&self, map: OrderedMap) -> Result<Value, LoxRuntimeException> {
+        self.allocate(map.len() * mem::size_of::<(Key, Value)>())?;
+
+        let map: Map = Rc::new(RefCell::new(map));
+        self.runtime.heap.track(Object::Map(map.clone()));
+
+        Ok(Value::Map(map))
+    }
+
+    /// Creates a generator running the body in the given environment.
+    pub fn new_generator(
+        &self,
+        body: Declaration,
+        environment: Environment,
+    ) -> Result<Value, LoxRuntimeException> {
+        self.allocate(mem::size_of::<Machine>())?;
+
+        let machine = Machine::new(vec![body], environment);
+        let generator: Generator = Rc::new(RefCell::new(machine));
+        self.runtime
+            .heap
+            .track(Object::Generator(generator.clone()));
+
+        Ok(Value::Generator(generator))
+    }
+
+    /// Creates a pending future, settled by whoever it is handed to.
+    pub fn new_future(&self) -> Result<Future, LoxRuntimeException> {
+        self.allocate(mem::size_of::<FutureState>())?;
+
+        let future: Future = Rc::new(RefCell::new(FutureState::Pending));
+        self.runtime.heap.track(Object::Future(future.clone()));
+
+        Ok(future)
+    }
+
+    /// Creates a channel shared by reference.
+    pub fn new_channel(&self) -> Result<Value, LoxRuntimeException> {
+        self.allocate(mem::size_of::<ChannelState>())?;
+
+        let channel: Channel = Rc::new(RefCell::new(ChannelState::default()));
+        self.runtime.heap.track(Object::Channel(channel.clone()));
+
+        Ok(Value::Channel(channel))
+    }
+
+    /// Defines the natives of tasks, which only an event loop runs.
+    pub fn define_task_natives(&self) {
+        self.define_native("spawn", 1, spawn);
+        self.define_native("sleep", 1, sleep);
+        self.define_native("channel", 0, channel);
+        self.define_native("send", 2, send);
+        self.define_native("receive", 1, receive);
+    }
+
+    /// Empties this scope, dropping every value it defined.
+    pub fn clear(&self) {
+        let locals = mem::take(&mut *self.locals.borrow_mut());
+        drop(locals);
+    }
+
+    pub fn collect_garbage(&self) {
+        self.runtime.heap.collect();
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.runtime.heap.stats()
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    pub fn require(&self, capability: Capability, native: &str) -> Result<(), LoxRuntimeException> {
+        if self.runtime.capabilities.contains(&capability) {
+            Ok(())
+        } else {
+            Err(LoxRuntimeException::Error(format!(
+                "{} needs the {} capability, which is not allowed",
+                native, capability
+            )))
+        }
+    }
+
+    pub fn allocate(&self, bytes: usize) -> Result<(), LoxRuntimeException> {
+        self.runtime.heap.allocate(bytes)
+    }
+
+    fn define_native(&self, name: &str, arity: usize, native: Native) {
+        let callable = Value::NativeCallable(name.to_owned(), arity, native);
+        self.define(name.to_owned(), Some(callable));
+    }
+
     pub fn define(&self, k: String, v: Option<Value>) {
-        self.locals.borrow_mut().insert(k, v.unwrap_or(Value::Nil));
+        let binding = Binding {
+            value: v.unwrap_or(Value::Nil),
+            constant: false,
+        };
+        self.locals.borrow_mut().insert(k, binding);
+    }
+
+    /// Defines a name declared by the script, which can't replace a
+    /// constant of this scope.
+    pub fn declare(
+        &self,
+        k: String,
+        v: Option<Value>,
+        constant: bool,
+    ) -> Result<(), LoxRuntimeException> {
+        let mut locals = self.locals.borrow_mut();
+
+        if locals.get(&k).is_some_and(|binding| binding.constant) {
+            return Err(LoxRuntimeException::Error(format!(
+                "Can't redeclare constant {}",
+                k
+            )));
+        }
+
+        let binding = Binding {
+            value: v.unwrap_or(Value::Nil),
+            constant,
+        };
+        locals.insert(k, binding);
+
+        Ok(())
     }
 
     pub fn assign(&self, k: String, v: Value) -> Result<(), LoxRuntimeException> {
-        if self.locals.borrow().contains_key(&k) {
-            self.locals.borrow_mut().insert(k, v);
+        let constant = self.locals.borrow().get(&k).map(|binding| binding.constant);
+
+        if constant == Some(true) {
+            Err(LoxRuntimeException::Error(format!(
+                "Can't assign to constant {}",
+                k
+            )))
+        } else if constant.is_some() {
+            self.locals.borrow_mut().insert(
+                k,
+                Binding {
+                    value: v,
+                    constant: false,
+                },
+            );
             Ok(())
         } else if let Some(enclosing_environment) = &self.enclosing {
             enclosing_environment.assign(k, v)
@@ -55,7 +265,7 @@ impl Environment {
         let v = map.get(k);
 
         if let Some(v) = v {
-            Ok(v.to_owned())
+            Ok(v.value.to_owned())
         } else if let (None, Some(enclosing_environment)) = (v, &self.enclosing) {
             enclosing_environment.get(k)
         } else {
@@ -46,15 +46,49 @@ impl Report {
 
 pub enum LoxRuntimeException {
     Error(String),
+    /// A runtime error, once it went through the statement that raised it.
+    Located(String, Line),
+    /// A value thrown with `throw`, and the line it was thrown from.
+    Throw(Value, Line),
     Return(Value),
+    /// `return f(x);`, run by the caller of the returning function so that
+    /// tail calls don't grow the stack.
+    TailCall(Box<TailCall>),
+    Break,
+    Continue,
+}
+
+pub struct TailCall {
+    pub callee: Value,
+    pub args: Vec<Value>,
+    pub named: Vec<(String, Value)>,
+    pub line: Line,
+}
+
+impl LoxRuntimeException {
+    /// Gives a runtime error the line of the statement it went through.
+    pub fn at(self, line: Line) -> LoxRuntimeException {
+        match self {
+            LoxRuntimeException::Error(message) => LoxRuntimeException::Located(message, line),
+            exception => exception,
+        }
+    }
 }
 
 impl Display for LoxRuntimeException {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            // TODO: Add line handling
             LoxRuntimeException::Error(message) => write!(f, "Runtime error: {}", message),
+            LoxRuntimeException::Located(message, line) => {
+                write!(f, "[Line {}] Runtime error: {}", line, message)
+            }
+            LoxRuntimeException::Throw(value, line) => {
+                write!(f, "[Line {}] Uncaught exception: {}", line, value)
+            }
             LoxRuntimeException::Return(value) => write!(f, "Return value: {}", value),
+            LoxRuntimeException::TailCall(call) => write!(f, "Tail call to {}", call.callee),
+            LoxRuntimeException::Break => write!(f, "Break outside of a loop"),
+            LoxRuntimeException::Continue => write!(f, "Continue outside of a loop"),
         }
     }
 }
@@ -0,0 +1,402 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    compile,
+    config::Config,
+    environment::Environment,
+    error::LoxRuntimeException,
+    gc::{address, Future},
+    generator::{Machine, State},
+    interpreter::{caught_value, is_catchable},
+    value::Value,
+    Lox,
+};
+
+/// Runs scripts as cooperative tasks on a single thread, for hosts whose
+/// calls take time.
+///
+/// The embedder drives the loop: it runs the tasks until they all wait,
+/// hands them the results of its own calls, and moves the virtual time that
+/// `sleep` counts in. Nothing happens between two calls to the loop, which
+/// makes runs reproducible, and testable without real I/O.
+pub struct EventLoop {
+    lox: Lox,
+}
+
+/// A call from a script to a function of the host, waiting for the host to
+/// resolve or reject it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostCall {
+    pub id: u64,
+    pub name: String,
+    pub args: Vec<HostValue>,
+}
+
+/// The values exchanged with the host.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostValue {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(String),
+    List(Vec<HostValue>),
+}
+
+/// The result of an asynchronous operation, awaited by tasks.
+pub enum FutureState {
+    Pending,
+    Resolved(Value),
+    /// Awaiting it throws the value.
+    Rejected(Value),
+}
+
+/// An unbounded queue of values between tasks. Receivers wait for values in
+/// the order they asked for them.
+#[derive(Default)]
+pub struct ChannelState {
+    values: VecDeque<Value>,
+    receivers: VecDeque<Future>,
+}
+
+/// Tasks, timers and host calls of a run, shared with the natives that
+/// create them.
+#[derive(Default)]
+pub struct Scheduler {
+    /// Virtual time, in milliseconds.
+    now: f64,
+    tasks: VecDeque<Task>,
+    /// Deadlines and the futures they resolve, in the order they fire.
+    timers: Vec<(f64, Future)>,
+    host_calls: Vec<HostCall>,
+    pending: HashMap<u64, Future>,
+    next_id: u64,
+}
+
+struct Task {
+    machine: Machine,
+    /// Settled with the outcome of the task, for spawned functions.
+    result: Option<Future>,
+}
+
+impl EventLoop {
+    pub fn new(config: Config) -> EventLoop {
+        let lox = Lox::new(config);
+        lox.environment.define_task_natives();
+
+        EventLoop { lox }
+    }
+
+    /// Defines a global function that scripts call to reach the host. Each
+    /// call returns a future and shows up in `take_host_calls`.
+    pub fn define_host_function(&self, name: &str, arity: usize) {
+        let function = Value::HostFunction(name.to_owned(), arity);
+        self.lox.environment.define(name.to_owned(), Some(function));
+    }
+
+    /// Queues a script as a task. Its top-level statements run in the global
+    /// environment, and can `await`.
+    pub fn spawn(&self, source: &str) -> Result<()> {
+        let statements = match compile(source) {
+            Ok(statements) => statements,
+            Err(report) => {
+                print!("{}", report);
+                bail!("Failed compiling lox code");
+            }
+        };
+
+        let machine = Machine::new_task(statements, self.lox.environment.clone());
+        self.scheduler().tasks.push_back(Task {
+            machine,
+            result: None,
+        });
+
+        Ok(())
+    }
+
+    /// Runs tasks until each of them is done or waits for something that
+    /// is not there yet: a host call, a later timer, a value on a channel.
+    /// A spawned function that throws rejects its future, for the tasks
+    /// awaiting it to catch. Stops at the first script that fails, since
+    /// nothing can observe that failure.
+    pub fn run_until_idle(&self) -> Result<()> {
+        loop {
+            let task = self.scheduler().next_ready_task();
+            let Some(mut task) = task else {
+                return Ok(());
+            };
+
+            match task.machine.resume() {
+                Ok(State::Awaiting) => self.scheduler().tasks.push_back(task),
+                Ok(State::Returned(value)) => {
+                    if let Some(result) = &task.result {
+                        settle(result, Ok(value));
+                    }
+                }
+                Ok(State::Yielded(_)) => unreachable!("Yields only run in generators"),
+                Err(exception) => match &task.result {
+                    Some(result) if is_catchable(&exception) => {
+                        let value = caught_value(exception, &self.lox.environment)
+                            .unwrap_or_else(|exception| Value::String(exception.to_string()));
+                        settle(result, Err(value));
+                    }
+                    _ => {
+                        println!("{}", exception);
+                        bail!("Failed running lox task");
+                    }
+                },
+            }
+        }
+    }
+
+    /// Moves the virtual time forward, firing the timers on the way in
+    /// order, each followed by the tasks it woke up.
+    pub fn advance(&self, milliseconds: f64) -> Result<()> {
+        let deadline = self.now() + milliseconds;
+
+        self.run_until_idle()?;
+        loop {
+            let next = self.scheduler().timers.first().map(|(next, _)| *next);
+            match next {
+                Some(next) if next <= deadline => {
+                    self.scheduler().now = next;
+                    self.run_until_idle()?;
+                }
+                _ => break,
+            }
+        }
+        self.scheduler().now = deadline;
+
+        Ok(())
+    }
+
+    pub fn now(&self) -> f64 {
+        self.scheduler().now
+    }
+
+    /// Whether every task is done.
+    pub fn is_finished(&self) -> bool {
+        self.scheduler().tasks.is_empty()
+    }
+
+    /// The host calls made since the last time they were taken.
+    pub fn take_host_calls(&self) -> Vec<HostCall> {
+        mem::take(&mut self.scheduler().host_calls)
+    }
+
+    /// Settles a host call with a value. The tasks awaiting it run on the
+    /// next turn of the loop.
+    pub fn resolve(&self, id: u64, value: HostValue) -> Result<()> {
+        let future = self.take_pending(id)?;
+        let value = value
+            .into_value(&self.lox.environment)
+            .map_err(|exception| Value::String(exception.to_string()));
+        settle(&future, value);
+
+        Ok(())
+    }
+
+    /// Settles a host call with an error, thrown as a string by `await`.
+    pub fn reject(&self, id: u64, message: &str) -> Result<()> {
+        let future = self.take_pending(id)?;
+        settle(&future, Err(Value::String(message.to_owned())));
+
+        Ok(())
+    }
+
+    fn take_pending(&self, id: u64) -> Result<Future> {
+        match self.scheduler().pending.remove(&id) {
+            Some(future) => Ok(future),
+            None => bail!("No pending host call {}", id),
+        }
+    }
+
+    fn scheduler(&self) -> std::cell::RefMut<'_, Scheduler> {
+        self.lox.environment.runtime().scheduler.borrow_mut()
+    }
+}
+
+impl Drop for EventLoop {
+    fn drop(&mut self) {
+        // Suspended tasks hold environments the collector can't see into.
+        let scheduler = mem::take(&mut *self.scheduler());
+        drop(scheduler);
+    }
+}
+
+impl Scheduler {
+    /// Queues a spawned function, whose outcome settles the future.
+    pub fn spawn(&mut self, machine: Machine, result: Future) {
+        self.tasks.push_back(Task {
+            machine,
+            result: Some(result),
+        });
+    }
+
+    /// Resolves the future with nil once the virtual time reaches the delay.
+    pub fn sleep(&mut self, milliseconds: f64, future: Future) {
+        let deadline = self.now + milliseconds;
+        let position = self.timers.partition_point(|(other, _)| *other <= deadline);
+        self.timers.insert(position, (deadline, future));
+    }
+
+    /// Records a call for the host, which settles the future.
+    pub fn call_host(&mut self, name: String, args: Vec<HostValue>, future: Future) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.host_calls.push(HostCall { id, name, args });
+        self.pending.insert(id, future);
+    }
+
+    /// Fires the timers that are due, then takes the first task that can
+    /// make progress.
+    fn next_ready_task(&mut self) -> Option<Task> {
+        let due = self
+            .timers
+            .partition_point(|(deadline, _)| *deadline <= self.now);
+        for (_, future) in self.timers.drain(..due) {
+            settle(&future, Ok(Value::Nil));
+        }
+
+        let position = self.tasks.iter().position(|task| task.machine.is_ready())?;
+        self.tasks.remove(position)
+    }
+}
+
+impl FutureState {
+    /// The value of a resolved future, or the thrown value of a rejected
+    /// one, `None` while pending.
+    pub fn settled(&self) -> Option<Result<Value, Value>> {
+        match self {
+            FutureState::Pending => None,
+            FutureState::Resolved(value) => Some(Ok(value.clone())),
+            FutureState::Rejected(value) => Some(Err(value.clone())),
+        }
+    }
+
+    pub fn trace(&self, visit: &mut impl FnMut(usize)) {
+        if let FutureState::Resolved(value) | FutureState::Rejected(value) = self {
+            value.trace(visit);
+        }
+    }
+
+    pub fn take_values(&mut self) -> Vec<Value> {
+        match mem::replace(self, FutureState::Pending) {
+            FutureState::Pending => vec![],
+            FutureState::Resolved(value) | FutureState::Rejected(value) => vec![value],
+        }
+    }
+}
+
+/// Settles a pending future, a settled one keeping its first outcome.
+pub fn settle(future: &Future, result: Result<Value, Value>) {
+    let mut state = future.borrow_mut();
+    if matches!(*state, FutureState::Pending) {
+        *state = match result {
+            Ok(value) => FutureState::Resolved(value),
+            Err(value) => FutureState::Rejected(value),
+        };
+    }
+}
+
+impl ChannelState {
+    /// Hands the value to the oldest waiting receiver, or queues it.
+    pub fn send(&mut self, value: Value) {
+        match self.receivers.pop_front() {
+            Some(receiver) => settle(&receiver, Ok(value)),
+            None => self.values.push_back(value),
+        }
+    }
+
+    /// Resolves the future with the oldest queued value, or once one is sent.
+    pub fn receive(&mut self, future: Future) {
+        match self.values.pop_front() {
+            Some(value) => settle(&future, Ok(value)),
+            None => self.receivers.push_back(future),
+        }
+    }
+
+    /// The values sent and not received yet.
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.values.iter()
+    }
+
+    pub fn trace(&self, visit: &mut impl FnMut(usize)) {
+        self.values.iter().for_each(|value| value.trace(visit));
+        self.receivers
+            .iter()
+            .for_each(|receiver| visit(address(receiver)));
+    }
+
+    pub fn take_values(&mut self) -> Vec<Value> {
+        let receivers = mem::take(&mut self.receivers)
+            .into_iter()
+            .map(Value::Future);
+        mem::take(&mut self.values)
+            .into_iter()
+            .chain(receivers)
+            .collect()
+    }
+}
+
+impl HostValue {
+    pub fn from_value(value: &Value) -> Result<HostValue, LoxRuntimeException> {
+        HostValue::convert(value, &mut vec![])
+    }
+
+    /// Converts the value, failing on lists that contain themselves, which
+    /// the host has no way to represent.
+    fn convert(value: &Value, seen: &mut Vec<usize>) -> Result<HostValue, LoxRuntimeException> {
+        match value {
+            Value::Nil => Ok(HostValue::Nil),
+            Value::Boolean(b) => Ok(HostValue::Boolean(*b)),
+            Value::Integer(n) => Ok(HostValue::Integer(*n)),
+            Value::Number(n) => Ok(HostValue::Number(*n)),
+            Value::String(s) => Ok(HostValue::String(s.clone())),
+            Value::List(list) if seen.contains(&address(list)) => Err(LoxRuntimeException::Error(
+                format!("{} contains itself and can't be passed to the host", value),
+            )),
+            Value::List(list) => {
+                seen.push(address(list));
+                let items = list
+                    .borrow()
+                    .iter()
+                    .map(|item| HostValue::convert(item, seen))
+                    .collect::<Result<_, _>>();
+                seen.pop();
+
+                items.map(HostValue::List)
+            }
+            _ => Err(LoxRuntimeException::Error(format!(
+                "{} can't be passed to the host",
+                value
+            ))),
+        }
+    }
+
+    fn into_value(self, environment: &Environment) -> Result<Value, LoxRuntimeException> {
+        match self {
+            HostValue::Nil => Ok(Value::Nil),
+            HostValue::Boolean(b) => Ok(Value::Boolean(b)),
+            HostValue::Integer(n) => Ok(Value::Integer(n)),
+            HostValue::Number(n) => Ok(Value::Number(n)),
+            HostValue::String(s) => {
+                environment.allocate(s.len())?;
+                Ok(Value::String(s))
+            }
+            HostValue::List(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| item.into_value(environment))
+                    .collect::<Result<_, _>>()?;
+                environment.new_list(items)
+            }
+        }
+    }
+}
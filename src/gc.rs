@@ -0,0 +1,373 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    mem,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    environment::Binding,
+    error::LoxRuntimeException,
+    event_loop::{ChannelState, FutureState},
+    generator::Machine,
+    list::ListItems,
+    map::{Key, OrderedMap},
+    memory::Memory,
+    value::Value,
+};
+
+pub type Scope = RefCell<HashMap<String, Binding>>;
+pub type Locals = Rc<Scope>;
+pub type List = Rc<RefCell<ListItems>>;
+pub type Map = Rc<RefCell<OrderedMap>>;
+pub type Generator = Rc<RefCell<Machine>>;
+pub type Future = Rc<RefCell<FutureState>>;
+pub type Channel = Rc<RefCell<ChannelState>>;
+
+/// A container that can take part in a reference cycle.
+pub enum Object {
+    Scope(Locals),
+    List(List),
+    Map(Map),
+    Generator(Generator),
+    Future(Future),
+    Channel(Channel),
+}
+
+enum WeakObject {
+    Scope(Weak<Scope>),
+    List(Weak<RefCell<ListItems>>),
+    Map(Weak<RefCell<OrderedMap>>),
+    Generator(Weak<RefCell<Machine>>),
+    Future(Weak<RefCell<FutureState>>),
+    Channel(Weak<RefCell<ChannelState>>),
+}
+
+/// Heap values are reference counted, which frees everything except cycles,
+/// such as a closure stored in the scope it captures. The heap keeps a weak
+/// handle on every container that can take part in a cycle, and collects the
+/// ones that are only reachable from each other.
+pub struct Heap {
+    objects: RefCell<Vec<WeakObject>>,
+    memory: Memory,
+    stress: bool,
+    threshold: Cell<usize>,
+    collections: Cell<usize>,
+    freed: Cell<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Containers currently tracked by the collector.
+    pub objects: usize,
+    /// Number of collections run so far.
+    pub collections: usize,
+    /// Containers freed by the collector so far.
+    pub freed: usize,
+    /// Bytes held by the script: the ones live at the last collection, plus
+    /// the ones allocated since.
+    pub allocated: usize,
+}
+
+const INITIAL_THRESHOLD: usize = 1024;
+
+impl Heap {
+    /// With `stress`, a collection runs every time a container is tracked.
+    pub fn new(stress: bool, memory_limit: Option<usize>) -> Heap {
+        Heap {
+            objects: RefCell::new(vec![]),
+            memory: Memory::new(memory_limit),
+            stress,
+            threshold: Cell::new(INITIAL_THRESHOLD),
+            collections: Cell::new(0),
+            freed: Cell::new(0),
+        }
+    }
+
+    pub fn track(&self, object: Object) {
+        if self.stress || self.objects.borrow().len() >= self.threshold.get() {
+            self.collect();
+        }
+
+        self.objects.borrow_mut().push(object.downgrade());
+    }
+
+    /// Counts the bytes of a new value. Past the limit, a collection first
+    /// gives back the bytes of the values dropped since the last one.
+    pub fn allocate(&self, bytes: usize) -> Result<(), LoxRuntimeException> {
+        self.memory.allocate(bytes).or_else(|_| {
+            self.collect();
+            self.memory.allocate(bytes)
+        })
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            objects: self
+                .objects
+                .borrow()
+                .iter()
+                .filter(|object| object.upgrade().is_some())
+                .count(),
+            collections: self.collections.get(),
+            freed: self.freed.get(),
+            allocated: self.memory.allocated(),
+        }
+    }
+
+    /// Frees the containers that cannot be reached from outside the heap.
+    ///
+    /// Every strong reference to a container that is not held by another
+    /// tracked container comes from the interpreter itself (the Rust stack,
+    /// the host), so those containers are the roots. Everything marked from
+    /// them survives, the rest is emptied, which breaks the cycles and lets
+    /// reference counting free the memory. The survivors are then measured,
+    /// which gives back the bytes of everything dropped since the last
+    /// collection.
+    pub fn collect(&self) {
+        let objects: Vec<Object> = self
+            .objects
+            .borrow()
+            .iter()
+            .filter_map(WeakObject::upgrade)
+            .collect();
+
+        // Upgrading added one strong reference to each object.
+        let mut external: HashMap<usize, usize> = objects
+            .iter()
+            .map(|object| (object.address(), object.strong_count() - 1))
+            .collect();
+
+        for object in &objects {
+            let traced = object.trace(&mut |child| {
+                if let Some(count) = external.get_mut(&child) {
+                    *count -= 1;
+                }
+            });
+
+            // Borrowed somewhere up the stack, so it has to stay alive.
+            if !traced {
+                external.insert(object.address(), 1);
+            }
+        }
+
+        let by_address: HashMap<usize, &Object> = objects
+            .iter()
+            .map(|object| (object.address(), object))
+            .collect();
+        let mut marked: HashSet<usize> = HashSet::new();
+        let mut pending: Vec<&Object> = objects
+            .iter()
+            .filter(|object| external[&object.address()] > 0)
+            .collect();
+
+        while let Some(object) = pending.pop() {
+            if !marked.insert(object.address()) {
+                continue;
+            }
+
+            object.trace(&mut |child| {
+                if let Some(child) = by_address.get(&child) {
+                    pending.push(child);
+                }
+            });
+        }
+
+        let mut garbage = vec![];
+        let mut live = 0;
+        for object in &objects {
+            if marked.contains(&object.address()) {
+                live += object.size();
+            } else {
+                garbage.extend(object.take());
+            }
+        }
+        self.memory.reset(live);
+
+        self.collections.set(self.collections.get() + 1);
+        self.freed.set(self.freed.get() + garbage.len());
+
+        // Drop the garbage only once no container is borrowed anymore.
+        drop(garbage);
+        drop(objects);
+
+        let mut tracked = self.objects.borrow_mut();
+        tracked.retain(|object| object.upgrade().is_some());
+        self.threshold.set(INITIAL_THRESHOLD.max(tracked.len() * 2));
+    }
+}
+
+impl Object {
+    fn address(&self) -> usize {
+        match self {
+            Object::Scope(scope) => address(scope),
+            Object::List(list) => address(list),
+            Object::Map(map) => address(map),
+            Object::Generator(generator) => address(generator),
+            Object::Future(future) => address(future),
+            Object::Channel(channel) => address(channel),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Scope(scope) => Rc::strong_count(scope),
+            Object::List(list) => Rc::strong_count(list),
+            Object::Map(map) => Rc::strong_count(map),
+            Object::Generator(generator) => Rc::strong_count(generator),
+            Object::Future(future) => Rc::strong_count(future),
+            Object::Channel(channel) => Rc::strong_count(channel),
+        }
+    }
+
+    fn downgrade(&self) -> WeakObject {
+        match self {
+            Object::Scope(scope) => WeakObject::Scope(Rc::downgrade(scope)),
+            Object::List(list) => WeakObject::List(Rc::downgrade(list)),
+            Object::Map(map) => WeakObject::Map(Rc::downgrade(map)),
+            Object::Generator(generator) => WeakObject::Generator(Rc::downgrade(generator)),
+            Object::Future(future) => WeakObject::Future(Rc::downgrade(future)),
+            Object::Channel(channel) => WeakObject::Channel(Rc::downgrade(channel)),
+        }
+    }
+
+    /// Visits the addresses of the containers directly referenced by this
+    /// one. Returns false when the container is mutably borrowed.
+    fn trace(&self, visit: &mut impl FnMut(usize)) -> bool {
+        match self {
+            Object::Scope(scope) => scope
+                .try_borrow()
+                .map(|scope| {
+                    scope
+                        .values()
+                        .for_each(|binding| binding.value.trace(visit))
+                })
+                .is_ok(),
+            Object::List(list) => list
+                .try_borrow()
+                .map(|list| list.iter().for_each(|value| value.trace(visit)))
+                .is_ok(),
+            Object::Map(map) => map
+                .try_borrow()
+                .map(|map| map.iter().for_each(|(_, value)| value.trace(visit)))
+                .is_ok(),
+            Object::Generator(generator) => generator
+                .try_borrow()
+                .map(|generator| generator.trace(visit))
+                .is_ok(),
+            Object::Future(future) => future
+                .try_borrow()
+                .map(|future| future.trace(visit))
+                .is_ok(),
+            Object::Channel(channel) => channel
+                .try_borrow()
+                .map(|channel| channel.trace(visit))
+                .is_ok(),
+        }
+    }
+
+    /// Bytes held by the container, counted as they are charged when
+    /// allocated. A borrowed container counts as empty.
+    fn size(&self) -> usize {
+        match self {
+            Object::Scope(scope) => scope.try_borrow().map_or(0, |scope| {
+                scope
+                    .values()
+                    .map(|binding| value_size(&binding.value))
+                    .sum()
+            }),
+            Object::List(list) => list.try_borrow().map_or(0, |list| {
+                list.iter()
+                    .map(|item| mem::size_of::<Value>() + value_size(item))
+                    .sum()
+            }),
+            Object::Map(map) => map.try_borrow().map_or(0, |map| {
+                map.iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            Key::String(s) => s.len(),
+                            _ => 0,
+                        };
+                        mem::size_of::<(Key, Value)>() + key + value_size(value)
+                    })
+                    .sum()
+            }),
+            Object::Generator(_) => mem::size_of::<Machine>(),
+            Object::Future(future) => {
+                let value = future.try_borrow().map_or(0, |future| match &*future {
+                    FutureState::Resolved(value) | FutureState::Rejected(value) => {
+                        value_size(value)
+                    }
+                    FutureState::Pending => 0,
+                });
+                mem::size_of::<FutureState>() + value
+            }
+            Object::Channel(channel) => {
+                let values: usize = channel.try_borrow().map_or(0, |channel| {
+                    channel
+                        .values()
+                        .map(|value| mem::size_of::<Value>() + value_size(value))
+                        .sum()
+                });
+                mem::size_of::<ChannelState>() + values
+            }
+        }
+    }
+
+    /// Empties the container, returning the values it held.
+    fn take(&self) -> Option<Vec<Value>> {
+        match self {
+            Object::Scope(scope) => scope.try_borrow_mut().ok().map(|mut scope| {
+                mem::take(&mut *scope)
+                    .into_values()
+                    .map(|binding| binding.value)
+                    .collect()
+            }),
+            Object::List(list) => list
+                .try_borrow_mut()
+                .ok()
+                .map(|mut list| list.take_values()),
+            Object::Map(map) => map.try_borrow_mut().ok().map(|mut map| map.take_values()),
+            Object::Generator(generator) => generator
+                .try_borrow_mut()
+                .ok()
+                .map(|mut generator| generator.take_values()),
+            Object::Future(future) => future
+                .try_borrow_mut()
+                .ok()
+                .map(|mut future| future.take_values()),
+            Object::Channel(channel) => channel
+                .try_borrow_mut()
+                .ok()
+                .map(|mut channel| channel.take_values()),
+        }
+    }
+}
+
+impl WeakObject {
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            WeakObject::Scope(scope) => scope.upgrade().map(Object::Scope),
+            WeakObject::List(list) => list.upgrade().map(Object::List),
+            WeakObject::Map(map) => map.upgrade().map(Object::Map),
+            WeakObject::Generator(generator) => generator.upgrade().map(Object::Generator),
+            WeakObject::Future(future) => future.upgrade().map(Object::Future),
+            WeakObject::Channel(channel) => channel.upgrade().map(Object::Channel),
+        }
+    }
+}
+
+/// Bytes a value holds apart from the containers it refers to, which are
+/// measured on their own.
+fn value_size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::Callable(..) | Value::Enum(_) => mem::size_of::<Value>(),
+        _ => 0,
+    }
+}
+
+/// Identifies a container by the address of its contents.
+pub fn address<T>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
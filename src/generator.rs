@@ -0,0 +1,635 @@
+use std::{collections::VecDeque, mem};
+
+use crate::{
+    environment::Environment,
+    error::LoxRuntimeException,
+    gc::{address, Future},
+    grammar::{Argument, AwaitTarget, Declaration, Expression},
+    interpreter::{
+        caught_value, evaluate, evaluate_arguments, interpret, is_catchable, run_tail_call,
+        select_arm,
+    },
+    iterator::LoxIterator,
+    types::Line,
+    value::{bind_arguments, Value},
+};
+
+/// The suspended state of a generator or of a task: the statements it is
+/// running, innermost last.
+///
+/// `interpret` runs on the Rust stack, which can't be put aside at a
+/// `yield` or an `await`. Statements containing one are run frame by frame
+/// here instead, so that running can stop and resume later. Any other
+/// statement goes through `interpret` in one go.
+///
+/// Tasks also run the functions they call from a statement of its own on
+/// their frames: `f();`, `var x = f();`, `x = f();` or `return f();`, so that
+/// these functions, and the ones they call the same way, can await. Since
+/// any statement can make such a call, tasks run every block, condition and
+/// loop frame by frame.
+pub struct Machine {
+    frames: Vec<Frame>,
+    /// The `await` the machine is suspended on.
+    awaiting: Option<Awaiting>,
+    task: bool,
+}
+
+/// Where running a machine stopped.
+pub enum State {
+    Yielded(Value),
+    /// Suspended until the awaited future is settled.
+    Awaiting,
+    /// Done, with the returned value.
+    Returned(Value),
+}
+
+enum Frame {
+    /// Statements left to run, in the environment of their block.
+    Block(VecDeque<Declaration>, Environment),
+    While {
+        condition: Expression,
+        body: Declaration,
+        increment: Option<Expression>,
+        line: Line,
+        environment: Environment,
+        started: bool,
+    },
+    ForIn {
+        name: String,
+        iterator: LoxIterator,
+        body: Declaration,
+        line: Line,
+        environment: Environment,
+    },
+    /// A call of a function by a task, whose body runs in the frames
+    /// above. Its returned value goes to the target, or is returned again
+    /// from the calling function without one.
+    Call {
+        target: Option<AwaitTarget>,
+        environment: Environment,
+        line: Line,
+    },
+    /// A try statement whose body or handler is running. Its clauses are
+    /// taken as they start, and `completion` is the exception the `finally`
+    /// block raises again once done.
+    Try {
+        catch: Option<(String, Declaration)>,
+        finally: Option<Declaration>,
+        completion: Option<LoxRuntimeException>,
+        environment: Environment,
+    },
+}
+
+struct Awaiting {
+    target: AwaitTarget,
+    future: Future,
+    environment: Environment,
+    line: Line,
+}
+
+/// What an exception does to the innermost frame.
+enum Unwinding {
+    /// The frame handles it, running the given clause if any.
+    Handled(Option<(Declaration, Environment)>),
+    /// The frame is done: `break` out of its loop, or the return of its
+    /// call.
+    Exit,
+    /// The frame is dropped and the exception goes on.
+    Pass(LoxRuntimeException),
+}
+
+impl Machine {
+    pub fn new(statements: Vec<Declaration>, environment: Environment) -> Machine {
+        Machine {
+            frames: vec![Frame::Block(statements.into(), environment)],
+            awaiting: None,
+            task: false,
+        }
+    }
+
+    /// A machine run by the event loop, which can await.
+    pub fn new_task(statements: Vec<Declaration>, environment: Environment) -> Machine {
+        Machine {
+            task: true,
+            ..Machine::new(statements, environment)
+        }
+    }
+
+    /// Runs until the next `yield` or pending `await`. A machine that
+    /// returned or failed stays done, returning nil.
+    pub fn resume(&mut self) -> Result<State, LoxRuntimeException> {
+        let result = self.run();
+        if !matches!(result, Ok(State::Yielded(_) | State::Awaiting)) {
+            self.frames.clear();
+            self.awaiting = None;
+        }
+
+        result
+    }
+
+    /// Whether resuming would make progress, that is, unless the awaited
+    /// future is still pending.
+    pub fn is_ready(&self) -> bool {
+        self.awaiting
+            .as_ref()
+            .is_none_or(|awaiting| awaiting.future.borrow().settled().is_some())
+    }
+
+    fn run(&mut self) -> Result<State, LoxRuntimeException> {
+        if let Some(awaiting) = self.awaiting.take() {
+            let settled = awaiting.future.borrow().settled();
+            let Some(settled) = settled else {
+                self.awaiting = Some(awaiting);
+                return Ok(State::Awaiting);
+            };
+
+            if let Err(exception) = awaiting.bind(settled) {
+                if let Some(value) = self.unwind(exception)? {
+                    return Ok(State::Returned(value));
+                }
+            }
+        }
+
+        while let Some(frame) = self.frames.last_mut() {
+            let result = match next_statement(frame) {
+                Ok(Some((statement, environment))) => self.step(statement, environment),
+                Ok(None) => self
+                    .frames
+                    .pop()
+                    .map_or(Ok(None), |frame| frame.finish().map(|_| None)),
+                Err(exception) => Err(exception),
+            };
+
+            match result {
+                Ok(Some(state)) => return Ok(state),
+                Ok(None) => {}
+                Err(exception) => {
+                    if let Some(value) = self.unwind(exception)? {
+                        return Ok(State::Returned(value));
+                    }
+                }
+            }
+        }
+
+        Ok(State::Returned(Value::Nil))
+    }
+
+    /// Runs a statement, pushing frames for the ones that can suspend.
+    fn step(
+        &mut self,
+        statement: Declaration,
+        environment: Environment,
+    ) -> Result<Option<State>, LoxRuntimeException> {
+        if self.task {
+            if let Some((callee, arguments, target, line)) = call_statement(&statement) {
+                let (callee, arguments) = (callee.clone(), arguments.clone());
+                return self.call(callee, arguments, target, environment, line);
+            }
+        }
+
+        let in_frames = if self.task {
+            matches!(
+                statement,
+                Declaration::Await(_, _, _)
+                    | Declaration::Block(_)
+                    | Declaration::Documented(_, _)
+                    | Declaration::If(_, _, _, _)
+                    | Declaration::Match(_, _, _)
+                    | Declaration::While(_, _, _, _)
+                    | Declaration::ForIn(_, _, _, _)
+                    | Declaration::Try(_, _, _)
+            )
+        } else {
+            statement.can_suspend()
+        };
+        if !in_frames {
+            let result = interpret(statement, &environment);
+            return run_tail_call(result, &environment).map(|_| None);
+        }
+
+        match statement {
+            Declaration::Yield(expression, line) => evaluate(expression, &environment)
+                .map(|value| Some(State::Yielded(value)))
+                .map_err(|exception| exception.at(line)),
+            Declaration::Await(target, expression, line) => {
+                let future = match evaluate(expression, &environment) {
+                    Ok(Value::Future(future)) => future,
+                    Ok(value) => {
+                        return Err(LoxRuntimeException::Located(
+                            format!("Can only await futures, got {}", value),
+                            line,
+                        ))
+                    }
+                    Err(exception) => return Err(exception.at(line)),
+                };
+
+                let awaiting = Awaiting {
+                    target,
+                    future,
+                    environment,
+                    line,
+                };
+                let settled = awaiting.future.borrow().settled();
+                match settled {
+                    Some(settled) => awaiting.bind(settled).map(|_| None),
+                    None => {
+                        self.awaiting = Some(awaiting);
+                        Ok(Some(State::Awaiting))
+                    }
+                }
+            }
+            Declaration::Block(declarations) => {
+                let frame = Frame::Block(declarations.into(), environment.new_local());
+                self.frames.push(frame);
+                Ok(None)
+            }
+            Declaration::Documented(_, declaration) => self.step(*declaration, environment),
+            Declaration::If(condition, if_statement, else_statement, line) => {
+                let condition =
+                    evaluate(condition, &environment).map_err(|exception| exception.at(line))?;
+
+                if condition.is_truthy() {
+                    self.step(*if_statement, environment)
+                } else if let Some(else_statement) = else_statement {
+                    self.step(*else_statement, environment)
+                } else {
+                    Ok(None)
+                }
+            }
+            Declaration::Match(subject, arms, line) => {
+                let (body, arm_environment) = evaluate(subject, &environment)
+                    .and_then(|subject| select_arm(&subject, arms, &environment))
+                    .map_err(|exception| exception.at(line))?;
+
+                self.step(body, arm_environment)
+            }
+            Declaration::While(condition, body, increment, line) => {
+                self.frames.push(Frame::While {
+                    condition,
+                    body: *body,
+                    increment,
+                    line,
+                    environment,
+                    started: false,
+                });
+                Ok(None)
+            }
+            Declaration::ForIn(name, iterable, body, line) => {
+                let iterable = evaluate(iterable, &environment)
+                    .and_then(LoxIterator::new)
+                    .map_err(|exception| exception.at(line))?;
+
+                self.frames.push(Frame::ForIn {
+                    name,
+                    iterator: iterable,
+                    body: *body,
+                    line,
+                    environment,
+                });
+                Ok(None)
+            }
+            Declaration::Try(body, catch, finally) => {
+                self.frames.push(Frame::Try {
+                    catch: catch.map(|(name, handler)| (name, *handler)),
+                    finally: finally.map(|finally| *finally),
+                    completion: None,
+                    environment: environment.clone(),
+                });
+                self.frames
+                    .push(Frame::Block(VecDeque::from([*body]), environment));
+                Ok(None)
+            }
+            _ => unreachable!(
+                "Only blocks, conditions, matches, loops and tries contain a suspension"
+            ),
+        }
+    }
+
+    /// Calls a function, on frames of its own if it is written in Lox.
+    fn call(
+        &mut self,
+        callee: Expression,
+        arguments: Vec<Argument>,
+        target: Option<AwaitTarget>,
+        environment: Environment,
+        line: Line,
+    ) -> Result<Option<State>, LoxRuntimeException> {
+        let callee = evaluate(callee, &environment).map_err(|exception| exception.at(line))?;
+        let (args, named) =
+            evaluate_arguments(arguments, &environment).map_err(|exception| exception.at(line))?;
+
+        match &callee {
+            Value::Callable(name, closure, parameters, body) => {
+                let local_environment = closure.new_local();
+                bind_arguments(name, parameters, args, named, &local_environment)
+                    .map_err(|exception| exception.at(line))?;
+
+                self.frames.push(Frame::Call {
+                    target,
+                    environment,
+                    line,
+                });
+                let body = VecDeque::from([(**body).clone()]);
+                self.frames.push(Frame::Block(body, local_environment));
+                Ok(None)
+            }
+            _ => {
+                let value = callee
+                    .call_with_named(&environment, args, named)
+                    .map_err(|exception| exception.at(line))?;
+                hand_returned(target, value, &environment, line).map(|_| None)
+            }
+        }
+    }
+
+    /// Drops frames until one handles the exception. Returns the value of a
+    /// `return` that went through every frame, and the other exceptions
+    /// that did as an error.
+    fn unwind(
+        &mut self,
+        mut exception: LoxRuntimeException,
+    ) -> Result<Option<Value>, LoxRuntimeException> {
+        while let Some(frame) = self.frames.last_mut() {
+            match frame.unwind(exception) {
+                Unwinding::Handled(clause) => {
+                    if let Some((clause, environment)) = clause {
+                        let frame = Frame::Block(VecDeque::from([clause]), environment);
+                        self.frames.push(frame);
+                    }
+                    return Ok(None);
+                }
+                Unwinding::Exit => {
+                    self.frames.pop();
+                    return Ok(None);
+                }
+                Unwinding::Pass(passed) => {
+                    self.frames.pop();
+                    exception = passed;
+                }
+            }
+        }
+
+        match exception {
+            LoxRuntimeException::Return(value) => Ok(Some(value)),
+            exception => Err(exception),
+        }
+    }
+
+    /// Visits the containers the suspended statements keep alive.
+    pub fn trace(&self, visit: &mut impl FnMut(usize)) {
+        for frame in &self.frames {
+            match frame {
+                Frame::Block(_, environment)
+                | Frame::While { environment, .. }
+                | Frame::Call { environment, .. } => environment.trace(visit),
+                Frame::ForIn {
+                    iterator,
+                    environment,
+                    ..
+                } => {
+                    iterator.trace(visit);
+                    environment.trace(visit);
+                }
+                Frame::Try {
+                    completion,
+                    environment,
+                    ..
+                } => {
+                    if let Some(
+                        LoxRuntimeException::Throw(value, _) | LoxRuntimeException::Return(value),
+                    ) = completion
+                    {
+                        value.trace(visit);
+                    }
+                    environment.trace(visit);
+                }
+            }
+        }
+
+        if let Some(awaiting) = &self.awaiting {
+            visit(address(&awaiting.future));
+            awaiting.environment.trace(visit);
+        }
+    }
+
+    /// Ends the machine, returning the values it held.
+    pub fn take_values(&mut self) -> Vec<Value> {
+        let awaited = self
+            .awaiting
+            .take()
+            .map(|awaiting| Value::Future(awaiting.future));
+
+        mem::take(&mut self.frames)
+            .into_iter()
+            .flat_map(|frame| match frame {
+                Frame::ForIn { iterator, .. } => iterator.take_values(),
+                Frame::Try {
+                    completion:
+                        Some(
+                            LoxRuntimeException::Throw(value, _)
+                            | LoxRuntimeException::Return(value),
+                        ),
+                    ..
+                } => vec![value],
+                _ => vec![],
+            })
+            .chain(awaited)
+            .collect()
+    }
+}
+
+impl Frame {
+    fn unwind(&mut self, exception: LoxRuntimeException) -> Unwinding {
+        match (self, exception) {
+            (Frame::While { .. } | Frame::ForIn { .. }, LoxRuntimeException::Break) => {
+                Unwinding::Exit
+            }
+            (Frame::While { .. } | Frame::ForIn { .. }, LoxRuntimeException::Continue) => {
+                Unwinding::Handled(None)
+            }
+            (
+                Frame::Call {
+                    target: Some(target),
+                    environment,
+                    line,
+                },
+                LoxRuntimeException::Return(value),
+            ) => match bind(target.clone(), value, environment, *line) {
+                Ok(()) => Unwinding::Exit,
+                Err(exception) => Unwinding::Pass(exception),
+            },
+            (
+                Frame::Try {
+                    catch,
+                    finally,
+                    completion,
+                    environment,
+                },
+                mut exception,
+            ) => {
+                if is_catchable(&exception) {
+                    if let Some((name, handler)) = catch.take() {
+                        match caught_value(exception, environment) {
+                            Ok(value) => {
+                                let local_environment = environment.new_local();
+                                local_environment.define(name, Some(value));
+                                return Unwinding::Handled(Some((handler, local_environment)));
+                            }
+                            Err(error) => exception = error,
+                        }
+                    }
+                }
+
+                *catch = None;
+                match finally.take() {
+                    Some(finally) => {
+                        *completion = Some(exception);
+                        Unwinding::Handled(Some((finally, environment.clone())))
+                    }
+                    None => Unwinding::Pass(exception),
+                }
+            }
+            (_, exception) => Unwinding::Pass(exception),
+        }
+    }
+
+    /// Ends a frame once it has no statement left, raising again what its
+    /// `finally` block was run for, or returning nil from a call.
+    fn finish(self) -> Result<(), LoxRuntimeException> {
+        match self {
+            Frame::Try {
+                completion: Some(exception),
+                ..
+            } => Err(exception),
+            Frame::Call {
+                target,
+                environment,
+                line,
+            } => hand_returned(target, Value::Nil, &environment, line),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Awaiting {
+    fn bind(self, settled: Result<Value, Value>) -> Result<(), LoxRuntimeException> {
+        let value = settled.map_err(|value| LoxRuntimeException::Throw(value, self.line))?;
+        bind(self.target, value, &self.environment, self.line)
+    }
+}
+
+/// Stores the value of an `await` or of a call where the statement says.
+fn bind(
+    target: AwaitTarget,
+    value: Value,
+    environment: &Environment,
+    line: Line,
+) -> Result<(), LoxRuntimeException> {
+    match target {
+        AwaitTarget::Discard => Ok(()),
+        AwaitTarget::Var(name) => environment.declare(name, Some(value), false),
+        AwaitTarget::Assign(name) => environment.assign(name, value),
+    }
+    .map_err(|exception| exception.at(line))
+}
+
+/// Stores the value returned by a call, or returns it from the calling
+/// function for `return f();`.
+fn hand_returned(
+    target: Option<AwaitTarget>,
+    value: Value,
+    environment: &Environment,
+    line: Line,
+) -> Result<(), LoxRuntimeException> {
+    match target {
+        Some(target) => bind(target, value, environment, line),
+        None => Err(LoxRuntimeException::Return(value)),
+    }
+}
+
+/// The call a statement is made of, with where its value goes: `f();`,
+/// `var x = f();`, `x = f();`, or `return f();` which has no target.
+fn call_statement(
+    statement: &Declaration,
+) -> Option<(&Expression, &Vec<Argument>, Option<AwaitTarget>, Line)> {
+    let (call, target, line) = match statement {
+        Declaration::Expression(Expression::Assignment(name, value), line) => (
+            value.as_ref(),
+            Some(AwaitTarget::Assign(name.clone())),
+            line,
+        ),
+        Declaration::Expression(call, line) => (call, Some(AwaitTarget::Discard), line),
+        Declaration::Var(name, Some(call), line) => {
+            (call, Some(AwaitTarget::Var(name.clone())), line)
+        }
+        Declaration::Return(call, line) => (call, None, line),
+        _ => return None,
+    };
+
+    match call {
+        Expression::Call(callee, arguments) => Some((callee, arguments, target, *line)),
+        _ => None,
+    }
+}
+
+/// The next statement to run in a frame, with its environment, or `None`
+/// once the frame is done.
+fn next_statement(
+    frame: &mut Frame,
+) -> Result<Option<(Declaration, Environment)>, LoxRuntimeException> {
+    match frame {
+        Frame::Block(declarations, environment) => Ok(declarations
+            .pop_front()
+            .map(|declaration| (declaration, environment.clone()))),
+        Frame::While {
+            condition,
+            body,
+            increment,
+            line,
+            environment,
+            started,
+        } => {
+            if let (true, Some(increment)) = (*started, increment) {
+                evaluate(increment.clone(), environment)
+                    .map_err(|exception| exception.at(*line))?;
+            }
+            *started = true;
+
+            let condition = evaluate(condition.clone(), environment)
+                .map_err(|exception| exception.at(*line))?;
+
+            Ok(condition
+                .is_truthy()
+                .then(|| (body.clone(), environment.clone())))
+        }
+        Frame::ForIn {
+            name,
+            iterator,
+            body,
+            line,
+            environment,
+        } => {
+            let item = iterator
+                .next(environment)
+                .map_err(|exception| exception.at(*line))?;
+
+            Ok(item.map(|item| {
+                let local_environment = environment.new_local();
+                local_environment.define(name.clone(), Some(item));
+                (body.clone(), local_environment)
+            }))
+        }
+        Frame::Try {
+            catch,
+            finally,
+            environment,
+            ..
+        } => {
+            // The body or the handler is done: only `finally` is left.
+            *catch = None;
+            Ok(finally.take().map(|finally| (finally, environment.clone())))
+        }
+        // The body returned without a `return`.
+        Frame::Call { .. } => Ok(None),
+    }
+}
@@ -1,7 +1,14 @@
+use std::rc::Rc;
+
+use crate::types::Line;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
+    Integer(i64),
     Number(f64),
     String(String),
+    /// A string with embedded expressions, as in `"Hello ${name}"`.
+    Interpolation(Vec<Expression>),
     True,
     False,
     Nil,
@@ -16,22 +23,252 @@ pub enum Expression {
     Minus(Box<Expression>, Box<Expression>),
     Multiply(Box<Expression>, Box<Expression>),
     Divide(Box<Expression>, Box<Expression>),
+    /// `~/`, dividing and rounding towards negative infinity.
+    FloorDivide(Box<Expression>, Box<Expression>),
+    /// `%`, whose result has the sign of the divisor.
+    Modulo(Box<Expression>, Box<Expression>),
+    Power(Box<Expression>, Box<Expression>),
+    BitAnd(Box<Expression>, Box<Expression>),
+    BitOr(Box<Expression>, Box<Expression>),
+    BitXor(Box<Expression>, Box<Expression>),
+    BitNot(Box<Expression>),
+    ShiftLeft(Box<Expression>, Box<Expression>),
+    ShiftRight(Box<Expression>, Box<Expression>),
     Paren(Box<Expression>),
     Variable(String),
     Assignment(String, Box<Expression>),
     And(Box<Expression>, Box<Expression>),
     Or(Box<Expression>, Box<Expression>),
-    Call(Box<Expression>, Vec<Expression>),
+    Call(Box<Expression>, Vec<Argument>),
+    List(Vec<Expression>),
+    Map(Vec<(Expression, Expression)>),
+    Index(Box<Expression>, Box<Expression>),
+    IndexAssignment(Box<Expression>, Box<Expression>, Box<Expression>),
+    /// `target op= value`, the target being a variable or an index.
+    CompoundAssignment(Box<Expression>, CompoundOperator, Box<Expression>),
+    /// `condition ? then : else`.
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+    /// An anonymous function, with its parameters and block body.
+    Function(Vec<Parameter>, Box<Declaration>),
+    /// Reads a global of a module, as in `module.name`.
+    Get(Box<Expression>, String),
+}
+
+/// A function parameter, as in `b = 2` or `...rest`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Parameter {
+    pub name: String,
+    /// Evaluated at call time in the function's scope, when no argument is given.
+    pub default: Option<Expression>,
+    /// Collects the remaining positional arguments into a list.
+    pub rest: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Argument {
+    Positional(Expression),
+    /// `name: value`, only allowed after the positional arguments.
+    Named(String, Expression),
+}
+
+impl Argument {
+    pub fn value(&self) -> &Expression {
+        match self {
+            Argument::Positional(value) | Argument::Named(_, value) => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompoundOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// What an import binds in the importing scope.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Imported {
+    /// `import "path" as name;` binds the whole module.
+    Module(String),
+    /// `from "path" import a, b;` binds some of its globals.
+    Names(Vec<String>),
+}
+
+/// `enum Name { A, B(x, y) }`, shared by every value made from it.
+#[derive(Debug, PartialEq)]
+pub struct Enum {
+    pub name: String,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variant {
+    pub name: String,
+    /// Names of the fields, when the variant is declared with parentheses.
+    pub fields: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// `_`, matching anything without binding it.
+    Wildcard,
+    /// A name, bound to whatever it matches.
+    Binding(String),
+    /// A literal, matching the values equal to it.
+    Literal(Expression),
+    /// `Enum.Variant`, or `Enum.Variant(a, b)` matching the fields too.
+    Variant(String, String, Option<Vec<Pattern>>),
+    /// `[a, b]`, or `[a, ...rest]` binding the remaining items to a list.
+    List(Vec<Pattern>, Option<String>),
+}
+
+impl Pattern {
+    /// Whether the pattern matches every value.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard | Pattern::Binding(_))
+    }
+}
+
+/// `pattern if guard => body`, the guard being checked with the pattern's
+/// bindings defined.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Declaration,
+}
+
+/// Where the value of an `await` goes. `await` is a statement of its own, so
+/// that the tasks running it can be suspended between statements.
+#[derive(Debug, PartialEq, Clone)]
+pub enum AwaitTarget {
+    /// `await future;`
+    Discard,
+    /// `var name = await future;`
+    Var(String),
+    /// `name = await future;`
+    Assign(String),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Declaration {
-    Expression(Expression),
-    Print(Expression),
-    Var(String, Option<Expression>),
+    Expression(Expression, Line),
+    Print(Expression, Line),
+    Var(String, Option<Expression>, Line),
+    /// A binding that can't be assigned or declared again in its scope.
+    Const(String, Expression, Line),
     Block(Vec<Declaration>),
-    If(Expression, Box<Declaration>, Option<Box<Declaration>>),
-    While(Expression, Box<Declaration>),
-    Function(String, Vec<String>, Box<Declaration>),
-    Return(Expression),
+    If(Expression, Box<Declaration>, Option<Box<Declaration>>, Line),
+    /// The optional expression is the increment of a desugared `for` loop,
+    /// evaluated after each iteration, including the ones cut by `continue`.
+    While(Expression, Box<Declaration>, Option<Expression>, Line),
+    /// Runs the body once per item of the iterable, with the item bound to
+    /// the name in a fresh scope.
+    ForIn(String, Expression, Box<Declaration>, Line),
+    Function(String, Vec<Parameter>, Box<Declaration>, Line),
+    Enum(Rc<Enum>, Line),
+    /// Runs the body of the first arm matching the value.
+    Match(Expression, Vec<MatchArm>, Line),
+    Return(Expression, Line),
+    /// Hands a value to the caller of a generator, which resumes after it.
+    Yield(Expression, Line),
+    /// The body of a function containing a `yield`. Running it returns a
+    /// generator that runs the body as it is resumed.
+    Generator(Box<Declaration>),
+    /// Suspends the running task until the future is settled, then binds
+    /// its value.
+    Await(AwaitTarget, Expression, Line),
+    Throw(Expression, Line),
+    /// A protected block, with the name and block of the `catch` clause
+    /// and the `finally` block, at least one of the two being present.
+    Try(
+        Box<Declaration>,
+        Option<(String, Box<Declaration>)>,
+        Option<Box<Declaration>>,
+    ),
+    Break(Line),
+    Continue(Line),
+    Import(String, Imported, Line),
+    /// A declaration preceded by `///` comments, kept for documentation tools.
+    Documented(String, Box<Declaration>),
+}
+
+impl Declaration {
+    /// Line of the statements that evaluate expressions, used to locate
+    /// the runtime errors they raise.
+    pub fn line(&self) -> Option<Line> {
+        match self {
+            Declaration::Expression(_, line)
+            | Declaration::Print(_, line)
+            | Declaration::Var(_, _, line)
+            | Declaration::Const(_, _, line)
+            | Declaration::If(_, _, _, line)
+            | Declaration::While(_, _, _, line)
+            | Declaration::ForIn(_, _, _, line)
+            | Declaration::Return(_, line)
+            | Declaration::Yield(_, line)
+            | Declaration::Await(_, _, line)
+            | Declaration::Match(_, _, line)
+            | Declaration::Throw(_, line)
+            | Declaration::Function(_, _, _, line)
+            | Declaration::Enum(_, line)
+            | Declaration::Import(_, _, line) => Some(*line),
+            Declaration::Block(_)
+            | Declaration::Try(_, _, _)
+            | Declaration::Generator(_)
+            | Declaration::Break(_)
+            | Declaration::Continue(_) => None,
+            Declaration::Documented(_, declaration) => declaration.line(),
+        }
+    }
+
+    /// Whether a `yield` is in this statement, outside of nested functions.
+    pub fn contains_yield(&self) -> bool {
+        self.contains(&|declaration| matches!(declaration, Declaration::Yield(_, _)))
+    }
+
+    /// Whether running this statement can suspend, on a `yield` or an `await`.
+    pub fn can_suspend(&self) -> bool {
+        self.contains(&|declaration| {
+            matches!(
+                declaration,
+                Declaration::Yield(_, _) | Declaration::Await(_, _, _)
+            )
+        })
+    }
+
+    fn contains(&self, is_wanted: &impl Fn(&Declaration) -> bool) -> bool {
+        if is_wanted(self) {
+            return true;
+        }
+
+        match self {
+            Declaration::Block(declarations) => declarations
+                .iter()
+                .any(|declaration| declaration.contains(is_wanted)),
+            Declaration::If(_, if_statement, else_statement, _) => {
+                if_statement.contains(is_wanted)
+                    || else_statement
+                        .as_ref()
+                        .is_some_and(|else_statement| else_statement.contains(is_wanted))
+            }
+            Declaration::While(_, body, _, _) | Declaration::ForIn(_, _, body, _) => {
+                body.contains(is_wanted)
+            }
+            Declaration::Try(body, catch, finally) => {
+                body.contains(is_wanted)
+                    || catch
+                        .as_ref()
+                        .is_some_and(|(_, handler)| handler.contains(is_wanted))
+                    || finally
+                        .as_ref()
+                        .is_some_and(|finally| finally.contains(is_wanted))
+            }
+            Declaration::Match(_, arms, _) => arms.iter().any(|arm| arm.body.contains(is_wanted)),
+            Declaration::Documented(_, declaration) => declaration.contains(is_wanted),
+            _ => false,
+        }
+    }
 }
@@ -1,29 +1,48 @@
+use std::{cmp::Ordering, mem, rc::Rc};
+
 use crate::{
     environment::Environment,
-    error::LoxRuntimeException,
-    grammar::{Declaration, Expression},
+    error::{LoxRuntimeException, TailCall},
+    grammar::{Argument, CompoundOperator, Declaration, Expression, Imported, MatchArm, Pattern},
+    iterator::LoxIterator,
+    map::{Key, OrderedMap},
+    module::import,
+    number,
     value::Value,
 };
 pub fn interpret(
     declaration: Declaration,
     environment: &Environment,
 ) -> Result<(), LoxRuntimeException> {
+    let line = declaration.line();
+    let result = execute(declaration, environment);
+
+    match line {
+        Some(line) => result.map_err(|exception| exception.at(line)),
+        None => result,
+    }
+}
+
+fn execute(declaration: Declaration, environment: &Environment) -> Result<(), LoxRuntimeException> {
     match declaration {
-        Declaration::Print(expression) => {
+        Declaration::Print(expression, _) => {
             let value = evaluate(expression, environment);
             value.map(|value| println!("{}", value))?;
             Ok(())
         }
-        Declaration::Expression(expression) => {
+        Declaration::Expression(expression, _) => {
             evaluate(expression, environment)?;
             Ok(())
         }
-        Declaration::Var(name, value) => {
+        Declaration::Var(name, value, _) => {
             let value = value
                 .map(|value| evaluate(value, environment))
                 .transpose()?;
-            environment.define(name, value);
-            Ok(())
+            environment.declare(name, value, false)
+        }
+        Declaration::Const(name, value, _) => {
+            let value = evaluate(value, environment)?;
+            environment.declare(name, Some(value), true)
         }
         Declaration::Block(declarations) => {
             let local_environment = environment.new_local();
@@ -33,7 +52,7 @@ pub fn interpret(
 
             Ok(())
         }
-        Declaration::If(condition, if_statement, else_statement) => {
+        Declaration::If(condition, if_statement, else_statement, _) => {
             let condition = evaluate(condition, environment)?;
 
             if condition.is_truthy() {
@@ -44,24 +63,278 @@ pub fn interpret(
 
             Ok(())
         }
-        Declaration::While(condition, while_statement) => {
+        Declaration::While(condition, while_statement, increment, _) => {
             while evaluate(condition.clone(), environment)?.is_truthy() {
-                interpret(*while_statement.clone(), environment)?;
+                match interpret(*while_statement.clone(), environment) {
+                    Ok(()) | Err(LoxRuntimeException::Continue) => {}
+                    Err(LoxRuntimeException::Break) => break,
+                    Err(e) => return Err(e),
+                }
+
+                if let Some(increment) = &increment {
+                    evaluate(increment.clone(), environment)?;
+                }
             }
 
             Ok(())
         }
-        Declaration::Function(name, parameters, body) => {
-            let callable = Value::Callable(name.clone(), environment.clone(), parameters, *body);
-            environment.define(name, Some(callable));
+        Declaration::ForIn(name, iterable, body, _) => {
+            let iterable = evaluate(iterable, environment)?;
+            let mut iterator = LoxIterator::new(iterable)?;
+
+            while let Some(item) = iterator.next(environment)? {
+                let local_environment = environment.new_local();
+                local_environment.define(name.clone(), Some(item));
+
+                match interpret(*body.clone(), &local_environment) {
+                    Ok(()) | Err(LoxRuntimeException::Continue) => {}
+                    Err(LoxRuntimeException::Break) => break,
+                    Err(e) => return Err(e),
+                }
+            }
 
             Ok(())
         }
-        Declaration::Return(expression) => {
+        Declaration::Import(path, imported, _) => {
+            let module = import(&path, environment)?;
+
+            match imported {
+                Imported::Module(name) => environment.declare(name, Some(module), false)?,
+                Imported::Names(names) => {
+                    for name in names {
+                        let value = module.get(&name)?;
+                        environment.declare(name, Some(value), false)?;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+        Declaration::Break(_) => Err(LoxRuntimeException::Break),
+        Declaration::Continue(_) => Err(LoxRuntimeException::Continue),
+        Declaration::Documented(_, declaration) => execute(*declaration, environment),
+        Declaration::Function(name, parameters, body, _) => {
+            environment.allocate(mem::size_of::<Value>())?;
+            let callable = Value::Callable(
+                name.clone(),
+                environment.clone(),
+                parameters,
+                Rc::new(*body),
+            );
+            environment.declare(name, Some(callable), false)
+        }
+        Declaration::Enum(definition, _) => {
+            environment.allocate(mem::size_of::<Value>())?;
+            let name = definition.name.clone();
+            environment.declare(name, Some(Value::Enum(definition)), false)
+        }
+        Declaration::Match(subject, arms, _) => {
+            let subject = evaluate(subject, environment)?;
+            let (body, arm_environment) = select_arm(&subject, arms, environment)?;
+
+            interpret(body, &arm_environment)
+        }
+        Declaration::Return(Expression::Call(callee, arguments), line) => {
+            let callee = evaluate(*callee, environment)?;
+            let (args, named) = evaluate_arguments(arguments, environment)?;
+
+            Err(LoxRuntimeException::TailCall(Box::new(TailCall {
+                callee,
+                args,
+                named,
+                line,
+            })))
+        }
+        Declaration::Return(expression, _) => {
             let value = evaluate(expression, environment)?;
             Err(LoxRuntimeException::Return(value))
         }
+        Declaration::Generator(body) => {
+            let generator = environment.new_generator(*body, environment.clone())?;
+            Err(LoxRuntimeException::Return(generator))
+        }
+        Declaration::Yield(_, _) => unreachable!("Generators run their yields themselves"),
+        Declaration::Await(_, _, _) => Err(LoxRuntimeException::Error(
+            "Can only await in a task, or in a function it calls as a statement of its own."
+                .to_owned(),
+        )),
+        Declaration::Throw(expression, line) => {
+            let value = evaluate(expression, environment)?;
+            Err(LoxRuntimeException::Throw(value, line))
+        }
+        Declaration::Try(body, catch, finally) => {
+            // Tail calls are made inside the try, for their errors to be
+            // caught and for them to run before the finally block.
+            let result = run_tail_call(interpret(*body, environment), environment);
+            let result = match (result, catch) {
+                (Err(exception), Some((name, handler))) if is_catchable(&exception) => {
+                    caught_value(exception, environment).and_then(|exception| {
+                        let local_environment = environment.new_local();
+                        local_environment.define(name, Some(exception));
+                        let result = interpret(*handler, &local_environment);
+                        run_tail_call(result, environment)
+                    })
+                }
+                (result, _) => result,
+            };
+
+            match finally {
+                Some(finally) => interpret(*finally, environment).and(result),
+                None => result,
+            }
+        }
+    }
+}
+
+/// The body of the first arm matching the value, with the environment
+/// holding the arm's bindings.
+pub fn select_arm(
+    subject: &Value,
+    arms: Vec<MatchArm>,
+    environment: &Environment,
+) -> Result<(Declaration, Environment), LoxRuntimeException> {
+    for arm in arms {
+        let arm_environment = environment.new_local();
+        if !match_pattern(&arm.pattern, subject, &arm_environment)? {
+            continue;
+        }
+
+        if let Some(guard) = arm.guard {
+            if !evaluate(guard, &arm_environment)?.is_truthy() {
+                continue;
+            }
+        }
+
+        return Ok((arm.body, arm_environment));
     }
+
+    Err(LoxRuntimeException::Error(format!(
+        "No match arm for {}",
+        subject
+    )))
+}
+
+/// Whether the value matches the pattern, defining its bindings on the way.
+fn match_pattern(
+    pattern: &Pattern,
+    value: &Value,
+    environment: &Environment,
+) -> Result<bool, LoxRuntimeException> {
+    match pattern {
+        Pattern::Wildcard => Ok(true),
+        Pattern::Binding(name) => {
+            environment.define(name.clone(), Some(value.clone()));
+            Ok(true)
+        }
+        Pattern::Literal(literal) => Ok(evaluate(literal.clone(), environment)?.is_equal(value)),
+        Pattern::Variant(enum_name, variant_name, patterns) => {
+            let Value::Enum(definition) = environment.get(enum_name)? else {
+                return Err(LoxRuntimeException::Error(format!(
+                    "{} is not an enum",
+                    enum_name
+                )));
+            };
+            let Value::Variant(variant, index, fields) = value else {
+                return Ok(false);
+            };
+            if !Rc::ptr_eq(&definition, variant)
+                || definition.variants[*index].name != *variant_name
+            {
+                return Ok(false);
+            }
+
+            match (patterns, fields) {
+                (Some(patterns), Some(fields)) => {
+                    let fields = fields.borrow().clone();
+                    match_patterns(patterns, &fields, environment)
+                }
+                _ => Ok(true),
+            }
+        }
+        Pattern::List(patterns, rest) => {
+            let Value::List(list) = value else {
+                return Ok(false);
+            };
+            let items = list.borrow().clone();
+
+            let length_matches = match rest {
+                Some(_) => items.len() >= patterns.len(),
+                None => items.len() == patterns.len(),
+            };
+            if !length_matches || !match_patterns(patterns, &items, environment)? {
+                return Ok(false);
+            }
+
+            if let Some(rest) = rest {
+                let rest_items = items[patterns.len()..].to_vec();
+                let rest_items = environment.new_list(rest_items)?;
+                environment.define(rest.clone(), Some(rest_items));
+            }
+
+            Ok(true)
+        }
+    }
+}
+
+fn match_patterns(
+    patterns: &[Pattern],
+    values: &[Value],
+    environment: &Environment,
+) -> Result<bool, LoxRuntimeException> {
+    for (pattern, value) in patterns.iter().zip(values) {
+        if !match_pattern(pattern, value, environment)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Makes a pending tail call, turning its result into a return.
+pub fn run_tail_call(
+    result: Result<(), LoxRuntimeException>,
+    environment: &Environment,
+) -> Result<(), LoxRuntimeException> {
+    match result {
+        Err(LoxRuntimeException::TailCall(call)) => {
+            let value = call
+                .callee
+                .call_with_named(environment, call.args, call.named)
+                .map_err(|exception| exception.at(call.line))?;
+
+            Err(LoxRuntimeException::Return(value))
+        }
+        result => result,
+    }
+}
+
+pub fn is_catchable(exception: &LoxRuntimeException) -> bool {
+    matches!(
+        exception,
+        LoxRuntimeException::Throw(_, _)
+            | LoxRuntimeException::Error(_)
+            | LoxRuntimeException::Located(_, _)
+    )
+}
+
+/// The value bound by `catch`: the thrown value, or for runtime errors a map
+/// with their message and line.
+pub fn caught_value(
+    exception: LoxRuntimeException,
+    environment: &Environment,
+) -> Result<Value, LoxRuntimeException> {
+    let (message, line) = match exception {
+        LoxRuntimeException::Throw(value, _) => return Ok(value),
+        LoxRuntimeException::Located(message, line) => (message, Value::Integer(line as i64)),
+        LoxRuntimeException::Error(message) => (message, Value::Nil),
+        _ => unreachable!("Only catchable exceptions are caught"),
+    };
+
+    let mut error = OrderedMap::default();
+    error.insert(Key::String("message".to_owned()), Value::String(message));
+    error.insert(Key::String("line".to_owned()), line);
+
+    environment.new_map(error)
 }
 
 pub fn evaluate(
@@ -69,8 +342,21 @@ pub fn evaluate(
     environment: &Environment,
 ) -> Result<Value, LoxRuntimeException> {
     match expression {
+        Expression::Integer(n) => Ok(Value::Integer(n)),
         Expression::Number(n) => Ok(Value::Number(n)),
-        Expression::String(s) => Ok(Value::String(s)),
+        Expression::String(s) => {
+            environment.allocate(s.len())?;
+            Ok(Value::String(s))
+        }
+        Expression::Interpolation(parts) => {
+            let mut string = String::new();
+            for part in parts {
+                string.push_str(&evaluate(part, environment)?.stringify());
+            }
+
+            environment.allocate(string.len())?;
+            Ok(Value::String(string))
+        }
         Expression::True => Ok(Value::Boolean(true)),
         Expression::False => Ok(Value::Boolean(false)),
         Expression::Nil => Ok(Value::Nil),
@@ -81,63 +367,100 @@ pub fn evaluate(
         }
         Expression::Minus(left, right) => {
             let left = evaluate(*left, environment)?;
-            let left = left.as_number()?;
             let right = evaluate(*right, environment)?;
-            let right = right.as_number()?;
-            Ok(Value::Number(left - right))
+            number::arithmetic(&left, &right, number::subtract, |a, b| a - b)
         }
         Expression::Multiply(left, right) => {
             let left = evaluate(*left, environment)?;
-            let left = left.as_number()?;
             let right = evaluate(*right, environment)?;
-            let right = right.as_number()?;
-            Ok(Value::Number(left * right))
+            number::arithmetic(&left, &right, number::multiply, |a, b| a * b)
         }
         Expression::Divide(left, right) => {
             let left = evaluate(*left, environment)?;
-            let left = left.as_number()?;
             let right = evaluate(*right, environment)?;
-            let right = right.as_number()?;
-            Ok(Value::Number(left / right))
+            number::divide(&left, &right)
+        }
+        Expression::FloorDivide(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::arithmetic(&left, &right, number::floor_divide, |a, b| (a / b).floor())
+        }
+        Expression::Modulo(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::arithmetic(&left, &right, number::modulo, number::float_modulo)
+        }
+        Expression::Power(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::power(&left, &right)
+        }
+        Expression::BitAnd(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::bitwise(&left, &right, "&", |a, b| Ok(a & b))
         }
+        Expression::BitOr(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::bitwise(&left, &right, "|", |a, b| Ok(a | b))
+        }
+        Expression::BitXor(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::bitwise(&left, &right, "^", |a, b| Ok(a ^ b))
+        }
+        Expression::ShiftLeft(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::bitwise(&left, &right, "<<", number::shift_left)
+        }
+        Expression::ShiftRight(left, right) => {
+            let left = evaluate(*left, environment)?;
+            let right = evaluate(*right, environment)?;
+            number::bitwise(&left, &right, ">>", number::shift_right)
+        }
+        Expression::BitNot(expression) => match evaluate(*expression, environment)? {
+            Value::Integer(n) => Ok(Value::Integer(!n)),
+            value => Err(LoxRuntimeException::Error(format!(
+                "Operand of ~ must be an integer, got {}",
+                value
+            ))),
+        },
         Expression::Plus(left, right) => {
             let left = evaluate(*left, environment)?;
             let right = evaluate(*right, environment)?;
-            match (left, right) {
-                (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 + n2)),
-                (Value::String(s1), Value::String(s2)) => Ok(Value::String(s1 + &s2)),
-                _ => Err(LoxRuntimeException::Error(
-                    "Operands must be two numbers or two strings.".to_owned(),
-                )),
-            }
+            add(&left, &right, environment)
         }
         Expression::Greater(left, right) => {
             let left = evaluate(*left, environment)?;
-            let left = left.as_number()?;
             let right = evaluate(*right, environment)?;
-            let right = right.as_number()?;
-            Ok(Value::Boolean(left > right))
+            let ordering = number::compare(&left, &right)?;
+            Ok(Value::Boolean(ordering == Some(Ordering::Greater)))
         }
         Expression::GreaterEqual(left, right) => {
             let left = evaluate(*left, environment)?;
-            let left = left.as_number()?;
             let right = evaluate(*right, environment)?;
-            let right = right.as_number()?;
-            Ok(Value::Boolean(left >= right))
+            let ordering = number::compare(&left, &right)?;
+            Ok(Value::Boolean(matches!(
+                ordering,
+                Some(Ordering::Greater | Ordering::Equal)
+            )))
         }
         Expression::Less(left, right) => {
             let left = evaluate(*left, environment)?;
-            let left = left.as_number()?;
             let right = evaluate(*right, environment)?;
-            let right = right.as_number()?;
-            Ok(Value::Boolean(left < right))
+            let ordering = number::compare(&left, &right)?;
+            Ok(Value::Boolean(ordering == Some(Ordering::Less)))
         }
         Expression::LessEqual(left, right) => {
             let left = evaluate(*left, environment)?;
-            let left = left.as_number()?;
             let right = evaluate(*right, environment)?;
-            let right = right.as_number()?;
-            Ok(Value::Boolean(left <= right))
+            let ordering = number::compare(&left, &right)?;
+            Ok(Value::Boolean(matches!(
+                ordering,
+                Some(Ordering::Less | Ordering::Equal)
+            )))
         }
         Expression::Equal(left, right) => {
             let left = evaluate(*left, environment)?;
@@ -179,14 +502,183 @@ pub fn evaluate(
         Expression::Call(callee, arguments) => {
             let callee = evaluate(*callee, environment)?;
 
-            let mut evaluated_arguments = vec![];
-            for argument in arguments {
-                let argument = evaluate(argument, environment)?;
-                evaluated_arguments.push(argument);
+            let (args, named) = evaluate_arguments(arguments, environment)?;
+
+            callee.call_with_named(environment, args, named)
+        }
+        Expression::List(elements) => {
+            let mut items = vec![];
+            for element in elements {
+                items.push(evaluate(element, environment)?);
+            }
+
+            environment.new_list(items)
+        }
+        Expression::Function(parameters, body) => {
+            environment.allocate(mem::size_of::<Value>())?;
+
+            Ok(Value::Callable(
+                "anonymous".to_owned(),
+                environment.clone(),
+                parameters,
+                Rc::new(*body),
+            ))
+        }
+        Expression::Get(object, name) => {
+            let object = evaluate(*object, environment)?;
+            object.get(&name)
+        }
+        Expression::Map(entries) => {
+            let mut map = OrderedMap::default();
+            for (key, value) in entries {
+                let key = evaluate(key, environment)?;
+                let value = evaluate(value, environment)?;
+                map.insert(Key::new(&key)?, value);
             }
 
-            let result = callee.call(evaluated_arguments)?;
-            Ok(result)
+            environment.new_map(map)
+        }
+        Expression::Index(collection, index) => {
+            let collection = evaluate(*collection, environment)?;
+            let index = evaluate(*index, environment)?;
+
+            get_index(&collection, &index)
+        }
+        Expression::IndexAssignment(collection, index, value) => {
+            let collection = evaluate(*collection, environment)?;
+            let index = evaluate(*index, environment)?;
+            let value = evaluate(*value, environment)?;
+
+            set_index(&collection, &index, value.clone(), environment)?;
+            Ok(value)
+        }
+        // The target is evaluated once, so `list[f()] += 1` calls `f` once.
+        Expression::CompoundAssignment(target, operator, value) => match *target {
+            Expression::Variable(name) => {
+                let current = environment.get(&name)?;
+                let value = evaluate(*value, environment)?;
+                let result = apply_compound(operator, &current, &value, environment)?;
+
+                environment.assign(name, result.clone())?;
+                Ok(result)
+            }
+            Expression::Index(collection, index) => {
+                let collection = evaluate(*collection, environment)?;
+                let index = evaluate(*index, environment)?;
+                let current = get_index(&collection, &index)?;
+                let value = evaluate(*value, environment)?;
+                let result = apply_compound(operator, &current, &value, environment)?;
+
+                set_index(&collection, &index, result.clone(), environment)?;
+                Ok(result)
+            }
+            _ => unreachable!("Compound assignments only target variables and indexes"),
+        },
+        Expression::Conditional(condition, then_expression, else_expression) => {
+            if evaluate(*condition, environment)?.is_truthy() {
+                evaluate(*then_expression, environment)
+            } else {
+                evaluate(*else_expression, environment)
+            }
+        }
+    }
+}
+
+pub type Arguments = (Vec<Value>, Vec<(String, Value)>);
+
+/// Evaluates the positional and the named arguments of a call.
+pub fn evaluate_arguments(
+    arguments: Vec<Argument>,
+    environment: &Environment,
+) -> Result<Arguments, LoxRuntimeException> {
+    let mut args = vec![];
+    let mut named = vec![];
+    for argument in arguments {
+        match argument {
+            Argument::Positional(value) => args.push(evaluate(value, environment)?),
+            Argument::Named(name, value) => named.push((name, evaluate(value, environment)?)),
+        }
+    }
+
+    Ok((args, named))
+}
+
+fn add(
+    left: &Value,
+    right: &Value,
+    environment: &Environment,
+) -> Result<Value, LoxRuntimeException> {
+    match (left, right) {
+        (Value::String(s1), Value::String(s2)) => {
+            environment.allocate(s1.len() + s2.len())?;
+            Ok(Value::String(s1.clone() + s2))
+        }
+        (Value::Integer(_) | Value::Number(_), Value::Integer(_) | Value::Number(_)) => {
+            number::arithmetic(left, right, number::add, |a, b| a + b)
+        }
+        _ => Err(LoxRuntimeException::Error(
+            "Operands must be two numbers or two strings.".to_owned(),
+        )),
+    }
+}
+
+fn apply_compound(
+    operator: CompoundOperator,
+    left: &Value,
+    right: &Value,
+    environment: &Environment,
+) -> Result<Value, LoxRuntimeException> {
+    match operator {
+        CompoundOperator::Add => add(left, right, environment),
+        CompoundOperator::Subtract => {
+            number::arithmetic(left, right, number::subtract, |a, b| a - b)
+        }
+        CompoundOperator::Multiply => {
+            number::arithmetic(left, right, number::multiply, |a, b| a * b)
+        }
+        CompoundOperator::Divide => number::divide(left, right),
+    }
+}
+
+fn get_index(collection: &Value, index: &Value) -> Result<Value, LoxRuntimeException> {
+    match collection {
+        Value::Map(map) => {
+            let key = Key::new(index)?;
+            map.borrow()
+                .get(&key)
+                .cloned()
+                .ok_or_else(|| LoxRuntimeException::Error(format!("Undefined key {}", index)))
+        }
+        _ => {
+            let list = collection.as_list()?.borrow();
+            let index = index.as_index(list.len())?;
+
+            Ok(list[index].clone())
+        }
+    }
+}
+
+fn set_index(
+    collection: &Value,
+    index: &Value,
+    value: Value,
+    environment: &Environment,
+) -> Result<(), LoxRuntimeException> {
+    match collection {
+        Value::Map(map) => {
+            let key = Key::new(index)?;
+            if !map.borrow().contains_key(&key) {
+                environment.allocate(mem::size_of::<(Key, Value)>())?;
+            }
+            map.borrow().check_mutable()?;
+            map.borrow_mut().insert(key, value);
+        }
+        _ => {
+            let mut list = collection.as_list()?.borrow_mut();
+            let index = index.as_index(list.len())?;
+            list.items_mut()?[index] = value;
         }
     }
+
+    Ok(())
 }
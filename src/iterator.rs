@@ -0,0 +1,88 @@
+use std::mem;
+
+use crate::{
+    environment::Environment,
+    error::LoxRuntimeException,
+    gc::{address, List},
+    value::Value,
+};
+
+/// What a `for (x in iterable)` loop walks through.
+///
+/// Lists are read live, so items pushed while looping are visited too. Map
+/// keys and string characters are copied when the loop starts. Any callable
+/// taking no argument is an iterator, generators included: it is called
+/// until it returns nil.
+pub enum LoxIterator {
+    List(List, usize),
+    Values(Vec<Value>, usize),
+    Callable(Value),
+}
+
+impl LoxIterator {
+    pub fn new(iterable: Value) -> Result<LoxIterator, LoxRuntimeException> {
+        match iterable {
+            Value::List(list) => Ok(LoxIterator::List(list, 0)),
+            Value::Map(map) => {
+                let keys = map.borrow().keys().map(|key| key.to_value()).collect();
+                Ok(LoxIterator::Values(keys, 0))
+            }
+            Value::String(s) => {
+                let characters = s.chars().map(|c| Value::String(c.to_string())).collect();
+                Ok(LoxIterator::Values(characters, 0))
+            }
+            Value::Callable(_, _, _, _) | Value::NativeCallable(_, _, _) | Value::Generator(_) => {
+                Ok(LoxIterator::Callable(iterable))
+            }
+            _ => Err(LoxRuntimeException::Error(format!(
+                "{} is not iterable",
+                iterable
+            ))),
+        }
+    }
+
+    pub fn next(
+        &mut self,
+        environment: &Environment,
+    ) -> Result<Option<Value>, LoxRuntimeException> {
+        match self {
+            LoxIterator::List(list, position) => {
+                let item = list.borrow().get(*position).cloned();
+                *position += 1;
+                Ok(item)
+            }
+            LoxIterator::Values(values, position) => {
+                let item = values
+                    .get_mut(*position)
+                    .map(|item| mem::replace(item, Value::Nil));
+                *position += 1;
+                if let Some(Value::String(s)) = &item {
+                    environment.allocate(s.len())?;
+                }
+                Ok(item)
+            }
+            LoxIterator::Callable(callable) => match callable.call(environment, vec![])? {
+                Value::Nil => Ok(None),
+                item => Ok(Some(item)),
+            },
+        }
+    }
+
+    /// Visits the containers the iterator keeps alive.
+    pub fn trace(&self, visit: &mut impl FnMut(usize)) {
+        match self {
+            LoxIterator::List(list, _) => visit(address(list)),
+            LoxIterator::Values(values, _) => values.iter().for_each(|value| value.trace(visit)),
+            LoxIterator::Callable(callable) => callable.trace(visit),
+        }
+    }
+
+    /// Empties the iterator, returning the values it held.
+    pub fn take_values(self) -> Vec<Value> {
+        match self {
+            LoxIterator::List(list, _) => vec![Value::List(list)],
+            LoxIterator::Values(values, _) => values,
+            LoxIterator::Callable(callable) => vec![callable],
+        }
+    }
+}
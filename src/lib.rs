@@ -1,42 +1,111 @@
+mod config;
 mod environment;
 mod error;
+mod event_loop;
+mod gc;
+mod generator;
 mod grammar;
 mod interpreter;
+mod iterator;
+mod list;
+mod map;
+mod memory;
+mod module;
+mod number;
 mod parser;
+mod random;
+mod resolver;
+mod runtime;
 mod scanner;
 mod source;
 mod standard;
+mod time;
 mod token;
 mod types;
 mod value;
 
 use anyhow::{Context, Result};
 use environment::Environment;
+use error::Report;
+use grammar::Declaration;
 use interpreter::interpret;
 use parser::parse;
+use resolver::resolve;
 use scanner::scan;
 use source::Source;
 use std::{
     fs,
     io::{self, Write},
+    path::Path,
 };
 use token::Tokens;
 
-pub fn run_file(filename: &str) -> Result<()> {
-    let file =
-        fs::read_to_string(&filename).context(format!("Failed reading file {}", filename))?;
-    let environment = Environment::new_global();
-    run(&file, &environment).context("Failed running lox code")
+pub use config::{Capability, Config};
+pub use event_loop::{EventLoop, HostCall, HostValue};
+pub use gc::HeapStats;
+pub use time::{FixedTimeSource, SystemTimeSource, TimeSource};
+
+/// An interpreter whose global environment lives across runs.
+pub struct Lox {
+    environment: Environment,
 }
 
-pub fn run_prompt() -> Result<()> {
-    let environment = Environment::new_global();
+impl Lox {
+    pub fn new(config: Config) -> Lox {
+        Lox {
+            environment: Environment::new_global(&config),
+        }
+    }
+
+    pub fn run(&self, source: &str) -> Result<()> {
+        run(source, &self.environment).context("Failed running lox code")
+    }
+
+    /// Runs a file, resolving its imports relative to its directory.
+    pub fn run_file(&self, filename: &str) -> Result<()> {
+        let file =
+            fs::read_to_string(filename).context(format!("Failed reading file {}", filename))?;
+        let path = Path::new(filename).canonicalize()?;
+
+        let modules = &self.environment.runtime().modules;
+        modules.borrow_mut().enter_file(path);
+        let result = self.run(&file);
+        modules.borrow_mut().leave_file();
+
+        result
+    }
+
+    pub fn collect_garbage(&self) {
+        self.environment.collect_garbage();
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.environment.heap_stats()
+    }
+}
+
+impl Drop for Lox {
+    fn drop(&mut self) {
+        // Globals are reachable from every closure defined at the top level,
+        // so they have to be emptied for those cycles to be collected.
+        self.environment.clear();
+        self.environment.runtime().modules.borrow_mut().clear();
+        self.environment.collect_garbage();
+    }
+}
+
+pub fn run_file(filename: &str, config: Config) -> Result<()> {
+    Lox::new(config).run_file(filename)
+}
+
+pub fn run_prompt(config: Config) -> Result<()> {
+    let lox = Lox::new(config);
 
     loop {
         let line = invite()?;
 
         if !line.is_empty() {
-            run(&line, &environment);
+            let _ = lox.run(&line);
         }
     }
 }
@@ -53,13 +122,7 @@ fn invite() -> Result<String> {
 
 // TODO: return a result to differentiate static vs runtime errors
 fn run(source: &str, environment: &Environment) -> Option<()> {
-    let source = Source::new(source);
-    let tokens = scan(source);
-
-    let tokens = tokens.map(Tokens::new);
-    let statements = tokens.and_then(parse);
-
-    match statements {
+    match compile(source) {
         Ok(statements) => {
             for statement in statements {
                 if let Err(error) = interpret(statement, environment) {
@@ -75,3 +138,13 @@ fn run(source: &str, environment: &Environment) -> Option<()> {
         }
     }
 }
+
+fn compile(source: &str) -> Result<Vec<Declaration>, Report> {
+    let source = Source::new(source);
+    let tokens = scan(source);
+
+    let tokens = tokens.map(Tokens::new);
+    tokens
+        .and_then(parse)
+        .and_then(|statements| resolve(&statements).map(|_| statements))
+}
@@ -0,0 +1,49 @@
+use std::{mem, ops::Deref};
+
+use crate::{error::LoxRuntimeException, value::Value};
+
+/// The items of a list. Reading them goes through `Deref`, changing them
+/// through `items_mut`, which refuses once the list is frozen.
+#[derive(Default)]
+pub struct ListItems {
+    items: Vec<Value>,
+    frozen: bool,
+}
+
+impl ListItems {
+    pub fn new(items: Vec<Value>) -> ListItems {
+        ListItems {
+            items,
+            frozen: false,
+        }
+    }
+
+    pub fn items_mut(&mut self) -> Result<&mut Vec<Value>, LoxRuntimeException> {
+        if self.frozen {
+            Err(LoxRuntimeException::Error(
+                "Can't modify a frozen list".to_owned(),
+            ))
+        } else {
+            Ok(&mut self.items)
+        }
+    }
+
+    /// Makes the list read-only for good. The items themselves stay as
+    /// mutable as they were.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Removes every item, frozen or not.
+    pub fn take_values(&mut self) -> Vec<Value> {
+        mem::take(&mut self.items)
+    }
+}
+
+impl Deref for ListItems {
+    type Target = Vec<Value>;
+
+    fn deref(&self) -> &Vec<Value> {
+        &self.items
+    }
+}
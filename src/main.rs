@@ -1,17 +1,33 @@
 use anyhow::Result;
 use std::{env, process};
 
-use rlox::{run_file, run_prompt};
+use rlox::{run_file, run_prompt, Config};
 
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let mut config = Config::default();
+    let mut scripts = vec![];
 
-    if args.len() == 2 {
-        run_file(&args[1]).or_else(|_| process::exit(65))
-    } else if args.len() == 1 {
-        run_prompt()
-    } else {
-        println!("Usage: rlox [script]");
-        process::exit(64);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--memory-limit" => {
+                let limit = args.next().and_then(|limit| limit.parse().ok());
+                config.memory_limit = Some(limit.unwrap_or_else(|| usage()));
+            }
+            "--deterministic" => config = config.deterministic(),
+            "--gc-stress" => config.gc_stress = true,
+            _ => scripts.push(arg),
+        }
     }
+
+    match &scripts[..] {
+        [] => run_prompt(config),
+        [script] => run_file(script, config).or_else(|_| process::exit(65)),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    println!("Usage: rlox [--memory-limit bytes] [--deterministic] [--gc-stress] [script]");
+    process::exit(64);
 }
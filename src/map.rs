@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::{error::LoxRuntimeException, value::Value};
+
+/// The values that can be used as map keys. Integral floats are stored as
+/// integers and other floats by their bits, so that keys follow
+/// `Value::is_equal`: `1` and `1.0` are the same key, as are `0` and `-0.0`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Number(u64),
+    String(String),
+}
+
+impl Key {
+    pub fn new(value: &Value) -> Result<Key, LoxRuntimeException> {
+        match value {
+            Value::Nil => Ok(Key::Nil),
+            Value::Boolean(b) => Ok(Key::Boolean(*b)),
+            Value::Number(n) if n.is_nan() => Err(LoxRuntimeException::Error(
+                "NaN can't be used as a map key".to_owned(),
+            )),
+            Value::Integer(n) => Ok(Key::Integer(*n)),
+            Value::Number(n)
+                if n.fract() == 0. && (i64::MIN as f64..i64::MAX as f64).contains(n) =>
+            {
+                Ok(Key::Integer(*n as i64))
+            }
+            Value::Number(n) => Ok(Key::Number(n.to_bits())),
+            Value::String(s) => Ok(Key::String(s.clone())),
+            _ => Err(LoxRuntimeException::Error(format!(
+                "{} can't be used as a map key",
+                value
+            ))),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Nil => Value::Nil,
+            Key::Boolean(b) => Value::Boolean(*b),
+            Key::Integer(n) => Value::Integer(*n),
+            Key::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            Key::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+/// A hash map remembering the order in which keys were first inserted, so
+/// that iterating over it gives the same result on every run.
+#[derive(Default, Clone)]
+pub struct OrderedMap {
+    entries: Vec<(Key, Value)>,
+    positions: HashMap<Key, usize>,
+    frozen: bool,
+}
+
+impl OrderedMap {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.positions
+            .get(key)
+            .map(|&position| &self.entries[position].1)
+    }
+
+    pub fn contains_key(&self, key: &Key) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    /// Returns true when the key was not in the map yet.
+    pub fn insert(&mut self, key: Key, value: Value) -> bool {
+        match self.positions.get(&key) {
+            Some(&position) => {
+                self.entries[position].1 = value;
+                false
+            }
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let position = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+
+        for (key, _) in &self.entries[position..] {
+            *self
+                .positions
+                .get_mut(key)
+                .expect("Every entry has a position") -= 1;
+        }
+
+        Some(value)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Key, Value)> {
+        self.entries.iter()
+    }
+
+    /// Makes the map read-only for good, which the callers changing it
+    /// check with `check_mutable`.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn check_mutable(&self) -> Result<(), LoxRuntimeException> {
+        if self.frozen {
+            Err(LoxRuntimeException::Error(
+                "Can't modify a frozen map".to_owned(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Removes every entry, returning the values.
+    pub fn take_values(&mut self) -> Vec<Value> {
+        self.positions.clear();
+        self.entries.drain(..).map(|(_, value)| value).collect()
+    }
+}
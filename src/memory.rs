@@ -0,0 +1,44 @@
+use std::cell::Cell;
+
+use crate::error::LoxRuntimeException;
+
+/// Counts the bytes held by a script for strings and heap objects. Dropped
+/// values are only given back when the heap measures what is still live,
+/// so the count is an upper bound between two collections.
+pub struct Memory {
+    limit: Option<usize>,
+    allocated: Cell<usize>,
+}
+
+impl Memory {
+    pub fn new(limit: Option<usize>) -> Memory {
+        Memory {
+            limit,
+            allocated: Cell::new(0),
+        }
+    }
+
+    pub fn allocate(&self, bytes: usize) -> Result<(), LoxRuntimeException> {
+        let allocated = self.allocated.get().saturating_add(bytes);
+
+        match self.limit {
+            Some(limit) if allocated > limit => Err(LoxRuntimeException::Error(format!(
+                "Memory limit of {} bytes exceeded",
+                limit
+            ))),
+            _ => {
+                self.allocated.set(allocated);
+                Ok(())
+            }
+        }
+    }
+
+    /// Replaces the count with the bytes measured by a collection.
+    pub fn reset(&self, live: usize) {
+        self.allocated.set(live);
+    }
+
+    pub fn allocated(&self) -> usize {
+        self.allocated.get()
+    }
+}
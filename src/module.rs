@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    compile, config::Capability, environment::Environment, error::LoxRuntimeException,
+    interpreter::interpret, value::Value,
+};
+
+/// Modules imported during a run, and the files being executed.
+#[derive(Default)]
+pub struct Modules {
+    /// Loaded modules by canonical path, `None` while a module is loading.
+    loaded: HashMap<PathBuf, Option<Value>>,
+    /// Files being executed, the innermost import last.
+    files: Vec<PathBuf>,
+}
+
+impl Modules {
+    pub fn enter_file(&mut self, path: PathBuf) {
+        self.files.push(path);
+    }
+
+    pub fn leave_file(&mut self) {
+        self.files.pop();
+    }
+
+    /// Forgets every module, so that their environments can be collected.
+    pub fn clear(&mut self) {
+        self.loaded.clear();
+    }
+
+    fn directory(&self) -> PathBuf {
+        self.files
+            .last()
+            .and_then(|file| file.parent())
+            .map(Path::to_path_buf)
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Loads the module at the given path, relative to the importing file, or
+/// returns it from the cache when it was already loaded.
+pub fn import(path: &str, environment: &Environment) -> Result<Value, LoxRuntimeException> {
+    environment.require(Capability::Filesystem, "import")?;
+
+    let modules = &environment.runtime().modules;
+    let full_path = modules.borrow().directory().join(path);
+    let full_path = full_path.canonicalize().map_err(|error| {
+        LoxRuntimeException::Error(format!("Can't find module {}: {}", path, error))
+    })?;
+
+    match modules.borrow().loaded.get(&full_path) {
+        Some(Some(module)) => return Ok(module.clone()),
+        Some(None) => {
+            let modules = modules.borrow();
+            let start = modules.files.iter().position(|file| *file == full_path);
+            let cycle: Vec<String> = modules.files[start.unwrap_or(0)..]
+                .iter()
+                .chain([&full_path])
+                .map(|file| file.display().to_string())
+                .collect();
+
+            return Err(LoxRuntimeException::Error(format!(
+                "Import cycle detected: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        None => {}
+    }
+
+    let source = fs::read_to_string(&full_path).map_err(|error| {
+        LoxRuntimeException::Error(format!("Failed reading module {}: {}", path, error))
+    })?;
+
+    modules.borrow_mut().loaded.insert(full_path.clone(), None);
+    modules.borrow_mut().enter_file(full_path.clone());
+
+    let module_environment = environment.new_module();
+    let result = run_module(path, &source, &module_environment);
+
+    let mut modules = modules.borrow_mut();
+    modules.leave_file();
+    match result {
+        Ok(()) => {
+            let module = Value::Module(path.to_owned(), module_environment);
+            modules.loaded.insert(full_path, Some(module.clone()));
+            Ok(module)
+        }
+        Err(error) => {
+            modules.loaded.remove(&full_path);
+            Err(error)
+        }
+    }
+}
+
+fn run_module(
+    path: &str,
+    source: &str,
+    environment: &Environment,
+) -> Result<(), LoxRuntimeException> {
+    let declarations = compile(source).map_err(|report| {
+        LoxRuntimeException::Error(format!("Failed compiling module {}:\n{}", path, report))
+    })?;
+
+    for declaration in declarations {
+        interpret(declaration, environment)?;
+    }
+
+    Ok(())
+}
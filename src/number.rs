@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+
+use crate::{error::LoxRuntimeException, value::Value};
+
+pub type IntegerOperation = fn(i64, i64) -> Result<i64, LoxRuntimeException>;
+pub type FloatOperation = fn(f64, f64) -> f64;
+
+/// Applies an arithmetic operator. Two integers give an exact integer, any
+/// float among the operands promotes both of them to floats.
+pub fn arithmetic(
+    left: &Value,
+    right: &Value,
+    integer: IntegerOperation,
+    float: FloatOperation,
+) -> Result<Value, LoxRuntimeException> {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => integer(*a, *b).map(Value::Integer),
+        _ => Ok(Value::Number(float(left.as_number()?, right.as_number()?))),
+    }
+}
+
+/// Orders two numbers, `None` meaning that one of them is NaN.
+pub fn compare(left: &Value, right: &Value) -> Result<Option<Ordering>, LoxRuntimeException> {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(Some(a.cmp(b))),
+        _ => Ok(left.as_number()?.partial_cmp(&right.as_number()?)),
+    }
+}
+
+pub fn add(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    a.checked_add(b).ok_or_else(|| overflow(a, "+", b))
+}
+
+pub fn subtract(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    a.checked_sub(b).ok_or_else(|| overflow(a, "-", b))
+}
+
+pub fn multiply(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    a.checked_mul(b).ok_or_else(|| overflow(a, "*", b))
+}
+
+/// Division always gives a float, even of two integers, so that `1 / 3`
+/// keeps its fraction. Integers are divided with `~/`.
+pub fn divide(left: &Value, right: &Value) -> Result<Value, LoxRuntimeException> {
+    Ok(Value::Number(left.as_number()? / right.as_number()?))
+}
+
+/// Integer division, truncating towards zero.
+fn truncate_divide(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    if b == 0 {
+        return Err(division_by_zero());
+    }
+
+    a.checked_div(b).ok_or_else(|| overflow(a, "~/", b))
+}
+
+/// Integer division, rounding towards negative infinity.
+pub fn floor_divide(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    let quotient = truncate_divide(a, b)?;
+
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Ok(quotient - 1)
+    } else {
+        Ok(quotient)
+    }
+}
+
+/// The remainder of the floor division, which has the sign of `b`.
+pub fn modulo(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    if b == 0 {
+        return Err(division_by_zero());
+    }
+
+    let remainder = a.wrapping_rem(b);
+    if remainder != 0 && (remainder < 0) != (b < 0) {
+        Ok(remainder + b)
+    } else {
+        Ok(remainder)
+    }
+}
+
+pub fn float_modulo(a: f64, b: f64) -> f64 {
+    let remainder = a % b;
+    if remainder != 0. && (remainder < 0.) != (b < 0.) {
+        remainder + b
+    } else {
+        remainder
+    }
+}
+
+/// Integers raised to a non-negative integer stay exact, a negative
+/// exponent gives a float.
+pub fn power(base: &Value, exponent: &Value) -> Result<Value, LoxRuntimeException> {
+    match (base, exponent) {
+        (Value::Integer(a), Value::Integer(b)) if *b >= 0 => u32::try_from(*b)
+            .ok()
+            .and_then(|b| a.checked_pow(b))
+            .map(Value::Integer)
+            .ok_or_else(|| overflow(*a, "**", *b)),
+        _ => Ok(Value::Number(base.as_number()?.powf(exponent.as_number()?))),
+    }
+}
+
+/// Applies a bitwise operator, which only takes integers.
+pub fn bitwise(
+    left: &Value,
+    right: &Value,
+    operator: &str,
+    operation: IntegerOperation,
+) -> Result<Value, LoxRuntimeException> {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => operation(*a, *b).map(Value::Integer),
+        _ => Err(LoxRuntimeException::Error(format!(
+            "Operands of {} must be integers, got {} and {}",
+            operator, left, right
+        ))),
+    }
+}
+
+pub fn shift_left(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    Ok(a << shift_amount(b)?)
+}
+
+/// Shifts right, keeping the sign.
+pub fn shift_right(a: i64, b: i64) -> Result<i64, LoxRuntimeException> {
+    Ok(a >> shift_amount(b)?)
+}
+
+fn shift_amount(b: i64) -> Result<i64, LoxRuntimeException> {
+    if (0..64).contains(&b) {
+        Ok(b)
+    } else {
+        Err(LoxRuntimeException::Error(format!(
+            "Shift amount {} is not between 0 and 63",
+            b
+        )))
+    }
+}
+
+fn overflow(a: i64, operator: &str, b: i64) -> LoxRuntimeException {
+    LoxRuntimeException::Error(format!("Integer overflow in {} {} {}", a, operator, b))
+}
+
+fn division_by_zero() -> LoxRuntimeException {
+    LoxRuntimeException::Error("Division by zero".to_owned())
+}
@@ -1,5 +1,6 @@
 mod declaration;
 mod expression;
+mod pattern;
 
 use self::declaration::parse_declaration;
 use crate::{error::Report, grammar::Declaration, token::Tokens};
@@ -13,7 +14,7 @@ pub fn parse(mut tokens: Tokens) -> Result<Vec<Declaration>, Report> {
         if let Err(error) = declaration {
             report.push(error);
 
-            if tokens.consume_until_semicolon() == None {
+            if tokens.consume_until_semicolon().is_none() {
                 break;
             }
         } else {
@@ -1,10 +1,14 @@
+use std::rc::Rc;
+
 use crate::grammar::Expression;
-use crate::parser::expression::parse_expression;
+use crate::parser::expression::{parse_expression, parse_statement_expression};
+use crate::parser::pattern::parse_pattern;
 
 use crate::{
     error::LoxError,
-    grammar::Declaration,
+    grammar::{AwaitTarget, Declaration, Enum, Imported, MatchArm, Parameter, Variant},
     token::{TokenType, Tokens},
+    types::Line,
 };
 
 pub fn parse_declaration(tokens: &mut Tokens) -> Result<Option<Declaration>, LoxError> {
@@ -14,30 +18,91 @@ pub fn parse_declaration(tokens: &mut Tokens) -> Result<Option<Declaration>, Lox
 
             Ok(None)
         }
-        TokenType::Var => Some(parse_var_declaration(tokens)).transpose(),
-        TokenType::Fun => Some(parse_fun_declaration(tokens)).transpose(),
+        TokenType::Var => Some(parse_documented(tokens, parse_var_declaration)).transpose(),
+        TokenType::Const => Some(parse_documented(tokens, parse_const_declaration)).transpose(),
+        TokenType::Enum => Some(parse_documented(tokens, parse_enum_declaration)).transpose(),
+        TokenType::Import | TokenType::From => Some(parse_import(tokens)).transpose(),
+        // `fun (` starts an anonymous function, used as an expression.
+        TokenType::Fun if tokens.peek_type_after(1) != TokenType::LeftParen => {
+            Some(parse_documented(tokens, parse_fun_declaration)).transpose()
+        }
         _ => Some(parse_statement(tokens)).transpose(),
     }
 }
 
+/// Keeps the doc comment written before the declaration, if any.
+fn parse_documented(
+    tokens: &mut Tokens,
+    parse: fn(&mut Tokens) -> Result<Declaration, LoxError>,
+) -> Result<Declaration, LoxError> {
+    let doc_comment = tokens.peek().doc_comment;
+    let declaration = parse(tokens)?;
+
+    Ok(match doc_comment {
+        Some(doc_comment) => Declaration::Documented(doc_comment, Box::new(declaration)),
+        None => declaration,
+    })
+}
+
+fn parse_import(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+
+    let declaration = if tokens.consume(TokenType::From).is_ok() {
+        let path = parse_module_path(tokens)?;
+        tokens.consume(TokenType::Import)?;
+
+        let mut names = vec![parse_var_name(tokens)?];
+        while tokens.consume(TokenType::Comma).is_ok() {
+            names.push(parse_var_name(tokens)?);
+        }
+
+        Declaration::Import(path, Imported::Names(names), line)
+    } else {
+        tokens.consume(TokenType::Import)?;
+        let path = parse_module_path(tokens)?;
+        tokens.consume(TokenType::As)?;
+        let name = parse_var_name(tokens)?;
+
+        Declaration::Import(path, Imported::Module(name), line)
+    };
+
+    tokens.consume(TokenType::Semicolon)?;
+
+    Ok(declaration)
+}
+
+fn parse_module_path(tokens: &mut Tokens) -> Result<String, LoxError> {
+    if let TokenType::String(path) = tokens.peek_type() {
+        tokens.next();
+
+        Ok(path)
+    } else {
+        let token = tokens.peek();
+        Err(LoxError {
+            line: token.line,
+            message: format!("Expected module path, got {} instead.", token.lexeme),
+        })
+    }
+}
+
 fn parse_fun_declaration(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
     tokens.consume(TokenType::Fun)?;
     parse_function(tokens)
 }
 
 fn parse_function(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
     if let TokenType::Identifier(function_name) = tokens.peek_type() {
         tokens.next();
 
-        tokens.consume(TokenType::LeftParen)?;
-        let parameters = parse_parameters(tokens).or(Ok(vec![]))?;
-        tokens.consume(TokenType::RightParen)?;
-        let body = parse_block(tokens)?;
+        let parameters = parse_parameter_list(tokens)?;
+        let body = parse_function_body(tokens)?;
 
         Ok(Declaration::Function(
             function_name,
             parameters,
             Box::new(body),
+            line,
         ))
     } else {
         let token = tokens.peek();
@@ -48,52 +113,165 @@ fn parse_function(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
     }
 }
 
-fn parse_parameters(tokens: &mut Tokens) -> Result<Vec<String>, LoxError> {
-    if let TokenType::Identifier(parameter) = tokens.peek_type() {
-        tokens.next();
+/// Parses parameters between parentheses, such as `(a, b = 2, ...rest)`.
+pub fn parse_parameter_list(tokens: &mut Tokens) -> Result<Vec<Parameter>, LoxError> {
+    tokens.consume(TokenType::LeftParen)?;
+    let parameters = if tokens.peek_type() == TokenType::RightParen {
+        vec![]
+    } else {
+        parse_parameters(tokens)?
+    };
+    tokens.consume(TokenType::RightParen)?;
 
-        let mut parameters = vec![parameter];
-        while tokens.peek_type() != TokenType::RightParen && tokens.peek_type() != TokenType::Eof {
-            tokens.consume(TokenType::Comma)?;
-
-            if let TokenType::Identifier(parameter) = tokens.peek_type() {
-                tokens.next();
-                parameters.push(parameter);
-            } else {
-                let token = tokens.peek();
-                return Err(LoxError {
-                    line: token.line,
-                    message: format!("Expected identifier, got {}.", token.lexeme),
-                });
-            }
+    Ok(parameters)
+}
+
+fn parse_parameters(tokens: &mut Tokens) -> Result<Vec<Parameter>, LoxError> {
+    let mut parameters: Vec<Parameter> = vec![parse_parameter(tokens)?];
+
+    while tokens.consume(TokenType::Comma).is_ok() {
+        let line = tokens.peek().line;
+        let parameter = parse_parameter(tokens)?;
+        let previous = parameters.last().expect("There is at least one parameter");
+
+        if previous.rest {
+            return Err(LoxError {
+                line,
+                message: format!("Rest parameter {} must be the last one.", previous.name),
+            });
+        }
+        if previous.default.is_some() && parameter.default.is_none() && !parameter.rest {
+            return Err(LoxError {
+                line,
+                message: format!(
+                    "Parameter {} needs a default value, as it follows one that has one.",
+                    parameter.name
+                ),
+            });
         }
 
-        Ok(parameters)
+        parameters.push(parameter);
+    }
+
+    Ok(parameters)
+}
+
+fn parse_parameter(tokens: &mut Tokens) -> Result<Parameter, LoxError> {
+    let rest = tokens.consume(TokenType::Ellipsis).is_ok();
+
+    let name = if let TokenType::Identifier(name) = tokens.peek_type() {
+        tokens.next();
+        name
     } else {
         let token = tokens.peek();
-        Err(LoxError {
+        return Err(LoxError {
             line: token.line,
             message: format!("Expected identifier, got {}.", token.lexeme),
-        })
+        });
+    };
+
+    let default = if !rest && tokens.consume(TokenType::Equal).is_ok() {
+        Some(parse_expression(tokens)?)
+    } else {
+        None
+    };
+
+    Ok(Parameter {
+        name,
+        default,
+        rest,
+    })
+}
+
+fn parse_enum_declaration(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    tokens.consume(TokenType::Enum)?;
+    let line = tokens.peek().line;
+    let name = parse_var_name(tokens)?;
+    tokens.consume(TokenType::LeftBrace)?;
+
+    let mut variants: Vec<Variant> = vec![];
+    while tokens.consume(TokenType::RightBrace).is_err() {
+        let line = tokens.peek().line;
+        let variant = parse_variant(tokens)?;
+        if variants.iter().any(|other| other.name == variant.name) {
+            return Err(LoxError {
+                line,
+                message: format!("Enum {} has two variants named {}.", name, variant.name),
+            });
+        }
+        variants.push(variant);
+
+        if tokens.consume(TokenType::Comma).is_err() {
+            tokens.consume(TokenType::RightBrace)?;
+            break;
+        }
     }
+
+    Ok(Declaration::Enum(Rc::new(Enum { name, variants }), line))
+}
+
+/// `Name`, or `Name(a, b)` for a variant holding fields.
+fn parse_variant(tokens: &mut Tokens) -> Result<Variant, LoxError> {
+    let name = parse_var_name(tokens)?;
+    if tokens.consume(TokenType::LeftParen).is_err() {
+        return Ok(Variant { name, fields: None });
+    }
+
+    let mut fields: Vec<String> = vec![];
+    while tokens.consume(TokenType::RightParen).is_err() {
+        let line = tokens.peek().line;
+        let field = parse_var_name(tokens)?;
+        if fields.contains(&field) {
+            return Err(LoxError {
+                line,
+                message: format!("Variant {} has two fields named {}.", name, field),
+            });
+        }
+        fields.push(field);
+
+        if tokens.consume(TokenType::Comma).is_err() {
+            tokens.consume(TokenType::RightParen)?;
+            break;
+        }
+    }
+
+    Ok(Variant {
+        name,
+        fields: Some(fields),
+    })
 }
 
 fn parse_var_declaration(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
     tokens.consume(TokenType::Var)?;
 
-    if let TokenType::Identifier(id) = tokens.peek_type() {
-        tokens.next();
+    let id = parse_var_name(tokens)?;
+    parse_var_initializer(tokens, id, line)
+}
 
-        let declaration = if tokens.consume(TokenType::Equal).is_ok() {
-            let expression = parse_expression(tokens)?;
-            Declaration::Var(id, Some(expression))
-        } else {
-            Declaration::Var(id, None)
-        };
+/// `const name = value;`, whose initializer can't be left out.
+fn parse_const_declaration(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+    tokens.consume(TokenType::Const)?;
+
+    let id = parse_var_name(tokens)?;
+    if tokens.consume(TokenType::Equal).is_err() {
+        return Err(LoxError {
+            line,
+            message: format!("Constant {} needs a value.", id),
+        });
+    }
+    let expression = parse_expression(tokens)?;
+    tokens.consume(TokenType::Semicolon)?;
 
-        tokens.consume(TokenType::Semicolon)?;
+    Ok(Declaration::Const(id, expression, line))
+}
+
+fn parse_var_name(tokens: &mut Tokens) -> Result<String, LoxError> {
+    if let TokenType::Identifier(id) = tokens.peek_type() {
+        tokens.next();
 
-        Ok(declaration)
+        Ok(id)
     } else {
         let token = tokens.peek();
         Err(LoxError {
@@ -103,6 +281,27 @@ fn parse_var_declaration(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
     }
 }
 
+fn parse_var_initializer(
+    tokens: &mut Tokens,
+    id: String,
+    line: Line,
+) -> Result<Declaration, LoxError> {
+    let declaration = if tokens.consume(TokenType::Equal).is_ok() {
+        if tokens.peek_type() == TokenType::Await {
+            return parse_await(tokens, AwaitTarget::Var(id));
+        }
+
+        let expression = parse_expression(tokens)?;
+        Declaration::Var(id, Some(expression), line)
+    } else {
+        Declaration::Var(id, None, line)
+    };
+
+    tokens.consume(TokenType::Semicolon)?;
+
+    Ok(declaration)
+}
+
 fn parse_statement(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
     match tokens.peek_type() {
         TokenType::Print => parse_print(tokens),
@@ -111,11 +310,27 @@ fn parse_statement(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
         TokenType::While => parse_while(tokens),
         TokenType::For => parse_for(tokens),
         TokenType::Return => parse_return(tokens),
+        TokenType::Break => parse_break(tokens),
+        TokenType::Continue => parse_continue(tokens),
+        TokenType::Throw => parse_throw(tokens),
+        TokenType::Yield => parse_yield(tokens),
+        TokenType::Try => parse_try(tokens),
+        TokenType::Match => parse_match(tokens),
+        TokenType::Await => parse_await(tokens, AwaitTarget::Discard),
+        TokenType::Identifier(name)
+            if tokens.peek_type_after(1) == TokenType::Equal
+                && tokens.peek_type_after(2) == TokenType::Await =>
+        {
+            tokens.next();
+            tokens.next();
+            parse_await(tokens, AwaitTarget::Assign(name))
+        }
         _ => parse_expression_statement(tokens),
     }
 }
 
 fn parse_return(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
     tokens.consume(TokenType::Return)?;
 
     let expression = match tokens.consume(TokenType::Semicolon) {
@@ -128,10 +343,88 @@ fn parse_return(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
         }
     };
 
-    Ok(Declaration::Return(expression))
+    Ok(Declaration::Return(expression, line))
+}
+
+fn parse_break(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+    tokens.consume(TokenType::Break)?;
+    tokens.consume(TokenType::Semicolon)?;
+
+    Ok(Declaration::Break(line))
+}
+
+fn parse_continue(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+    tokens.consume(TokenType::Continue)?;
+    tokens.consume(TokenType::Semicolon)?;
+
+    Ok(Declaration::Continue(line))
+}
+
+fn parse_throw(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+    tokens.consume(TokenType::Throw)?;
+    let expression = parse_expression(tokens)?;
+    tokens.consume(TokenType::Semicolon)?;
+
+    Ok(Declaration::Throw(expression, line))
+}
+
+fn parse_yield(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+    tokens.consume(TokenType::Yield)?;
+    let expression = parse_expression(tokens)?;
+    tokens.consume(TokenType::Semicolon)?;
+
+    Ok(Declaration::Yield(expression, line))
+}
+
+fn parse_await(tokens: &mut Tokens, target: AwaitTarget) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+    tokens.consume(TokenType::Await)?;
+    let expression = parse_expression(tokens)?;
+    tokens.consume(TokenType::Semicolon)?;
+
+    Ok(Declaration::Await(target, expression, line))
+}
+
+fn parse_try(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    tokens.consume(TokenType::Try)?;
+    let body = parse_block(tokens)?;
+
+    let catch = if tokens.consume(TokenType::Catch).is_ok() {
+        tokens.consume(TokenType::LeftParen)?;
+        let name = parse_var_name(tokens)?;
+        tokens.consume(TokenType::RightParen)?;
+
+        Some((name, Box::new(parse_block(tokens)?)))
+    } else {
+        None
+    };
+
+    let finally = if tokens.consume(TokenType::Finally).is_ok() {
+        Some(Box::new(parse_block(tokens)?))
+    } else {
+        None
+    };
+
+    if catch.is_none() && finally.is_none() {
+        let token = tokens.peek();
+        return Err(LoxError {
+            line: token.line,
+            message: format!(
+                "Expected 'catch' or 'finally', got {} instead.",
+                token.lexeme
+            ),
+        });
+    }
+
+    Ok(Declaration::Try(Box::new(body), catch, finally))
 }
 
 fn parse_for(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
     tokens.consume(TokenType::For)?;
 
     tokens.consume(TokenType::LeftParen)?;
@@ -141,8 +434,25 @@ fn parse_for(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
             tokens.next();
             None
         }
-        TokenType::Var => Some(parse_var_declaration(tokens)?),
-        _ => Some(parse_expression_statement(tokens)?),
+        TokenType::Var => {
+            tokens.next();
+
+            let id = parse_var_name(tokens)?;
+            if tokens.consume(TokenType::In).is_ok() {
+                return parse_for_in(tokens, id, line);
+            }
+
+            Some(parse_var_initializer(tokens, id, line)?)
+        }
+        _ => match parse_statement_expression(tokens)? {
+            Expression::Variable(id) if tokens.consume(TokenType::In).is_ok() => {
+                return parse_for_in(tokens, id, line);
+            }
+            expression => {
+                tokens.consume(TokenType::Semicolon)?;
+                Some(Declaration::Expression(expression, line))
+            }
+        },
     };
 
     let condition = if tokens.consume(TokenType::Semicolon).is_ok() {
@@ -157,21 +467,14 @@ fn parse_for(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
     let increment = if tokens.consume(TokenType::Semicolon).is_ok() {
         None
     } else {
-        Some(parse_expression(tokens)?)
+        Some(parse_statement_expression(tokens)?)
     };
     tokens.consume(TokenType::RightParen)?;
 
-    let mut body = parse_statement(tokens)?;
-
-    if let Some(increment) = increment {
-        body = Declaration::Block(vec![body, Declaration::Expression(increment)]);
-    }
+    let body = parse_statement(tokens)?;
 
-    if let Some(condition) = condition {
-        body = Declaration::While(condition, Box::new(body));
-    } else {
-        body = Declaration::While(Expression::True, Box::new(body));
-    }
+    let condition = condition.unwrap_or(Expression::True);
+    let mut body = Declaration::While(condition, Box::new(body), increment, line);
 
     if let Some(initializer) = initializer {
         body = Declaration::Block(vec![initializer, body])
@@ -180,14 +483,26 @@ fn parse_for(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
     Ok(body)
 }
 
+/// Parses the rest of `for (name in iterable) body`, after the `in` keyword.
+fn parse_for_in(tokens: &mut Tokens, name: String, line: Line) -> Result<Declaration, LoxError> {
+    let iterable = parse_expression(tokens)?;
+    tokens.consume(TokenType::RightParen)?;
+
+    let body = parse_statement(tokens)?;
+
+    Ok(Declaration::ForIn(name, iterable, Box::new(body), line))
+}
+
 fn parse_expression_statement(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
-    let expression = parse_expression(tokens)?;
+    let line = tokens.peek().line;
+    let expression = parse_statement_expression(tokens)?;
     tokens.consume(TokenType::Semicolon)?;
 
-    Ok(Declaration::Expression(expression))
+    Ok(Declaration::Expression(expression, line))
 }
 
 fn parse_while(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
     tokens.consume(TokenType::While)?;
 
     tokens.consume(TokenType::LeftParen)?;
@@ -196,10 +511,16 @@ fn parse_while(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
 
     let while_statement = parse_statement(tokens)?;
 
-    Ok(Declaration::While(condition, Box::new(while_statement)))
+    Ok(Declaration::While(
+        condition,
+        Box::new(while_statement),
+        None,
+        line,
+    ))
 }
 
 fn parse_if(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
     tokens.consume(TokenType::If)?;
 
     tokens.consume(TokenType::LeftParen)?;
@@ -214,27 +535,77 @@ fn parse_if(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
             condition,
             Box::new(if_statement),
             Some(Box::new(else_statement)),
+            line,
         ))
     } else {
-        Ok(Declaration::If(condition, Box::new(if_statement), None))
+        Ok(Declaration::If(
+            condition,
+            Box::new(if_statement),
+            None,
+            line,
+        ))
+    }
+}
+
+fn parse_match(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
+    tokens.consume(TokenType::Match)?;
+
+    tokens.consume(TokenType::LeftParen)?;
+    let subject = parse_expression(tokens)?;
+    tokens.consume(TokenType::RightParen)?;
+
+    tokens.consume(TokenType::LeftBrace)?;
+    let mut arms = vec![];
+    while tokens.consume(TokenType::RightBrace).is_err() {
+        let pattern = parse_pattern(tokens)?;
+        let guard = if tokens.consume(TokenType::If).is_ok() {
+            Some(parse_expression(tokens)?)
+        } else {
+            None
+        };
+        tokens.consume(TokenType::Arrow)?;
+        let body = parse_statement(tokens)?;
+
+        arms.push(MatchArm {
+            pattern,
+            guard,
+            body,
+        });
     }
+
+    Ok(Declaration::Match(subject, arms, line))
 }
 
 fn parse_print(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let line = tokens.peek().line;
     tokens.consume(TokenType::Print)?;
     let expression = parse_expression(tokens)?;
     tokens.consume(TokenType::Semicolon)?;
 
-    Ok(Declaration::Print(expression))
+    Ok(Declaration::Print(expression, line))
 }
 
-fn parse_block(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+/// Parses the block of a function, which is a generator when it yields.
+pub fn parse_function_body(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
+    let body = parse_block(tokens)?;
+
+    if body.contains_yield() {
+        Ok(Declaration::Generator(Box::new(body)))
+    } else {
+        Ok(body)
+    }
+}
+
+pub fn parse_block(tokens: &mut Tokens) -> Result<Declaration, LoxError> {
     tokens.consume(TokenType::LeftBrace)?;
 
     let mut declarations = vec![];
     while tokens.peek_type() != TokenType::RightBrace && tokens.peek_type() != TokenType::Eof {
         let declaration = parse_declaration(tokens)?; // FIXME: group errors together and parse all statements
-        declaration.map(|declaration| declarations.push(declaration));
+        if let Some(declaration) = declaration {
+            declarations.push(declaration);
+        }
     }
     tokens.consume(TokenType::RightBrace)?;
 
@@ -1,7 +1,9 @@
+use super::declaration::{parse_function_body, parse_parameter_list};
 use crate::{
     error::LoxError,
-    grammar::Expression,
-    token::{TokenType, Tokens},
+    grammar::{Argument, CompoundOperator, Declaration, Expression},
+    token::{StringPart, TokenType, Tokens},
+    types::Line,
 };
 
 pub fn parse_expression(tokens: &mut Tokens) -> Result<Expression, LoxError> {
@@ -9,18 +11,45 @@ pub fn parse_expression(tokens: &mut Tokens) -> Result<Expression, LoxError> {
 }
 
 fn parse_assignment(tokens: &mut Tokens) -> Result<Expression, LoxError> {
-    let left = parse_or(tokens)?;
+    let left = parse_conditional(tokens)?;
+
+    let operator = match tokens.peek_type() {
+        TokenType::PlusEqual => CompoundOperator::Add,
+        TokenType::MinusEqual => CompoundOperator::Subtract,
+        TokenType::StarEqual => CompoundOperator::Multiply,
+        TokenType::SlashEqual => CompoundOperator::Divide,
+        _ => return parse_simple_assignment(tokens, left),
+    };
+    let token = tokens.next();
+    check_assignment_target(&left, token.line)?;
+
+    let value = parse_assignment(tokens)?;
+    Ok(Expression::CompoundAssignment(
+        Box::new(left),
+        operator,
+        Box::new(value),
+    ))
+}
 
+fn parse_simple_assignment(tokens: &mut Tokens, left: Expression) -> Result<Expression, LoxError> {
     if tokens.consume(TokenType::Equal).is_ok() {
         if let Expression::Variable(name) = left {
             let assignment = parse_assignment(tokens)?;
 
             Ok(Expression::Assignment(name, Box::new(assignment)))
+        } else if let Expression::Index(list, index) = left {
+            let assignment = parse_assignment(tokens)?;
+
+            Ok(Expression::IndexAssignment(
+                list,
+                index,
+                Box::new(assignment),
+            ))
         } else {
             let token = tokens.peek();
             Err(LoxError {
                 line: token.line,
-                message: format!("Invalid assignment target."),
+                message: "Invalid assignment target.".to_owned(),
             })
         }
     } else {
@@ -28,119 +57,258 @@ fn parse_assignment(tokens: &mut Tokens) -> Result<Expression, LoxError> {
     }
 }
 
-fn parse_or(tokens: &mut Tokens) -> Result<Expression, LoxError> {
-    let left = parse_and(tokens)?;
+/// Parses an expression used as a statement, where `target++` and
+/// `target--` are also allowed.
+pub fn parse_statement_expression(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let expression = parse_expression(tokens)?;
+
+    if !is_postfix_step(tokens) {
+        return Ok(expression);
+    }
+    let operator = match tokens.next().token_type {
+        TokenType::Plus => CompoundOperator::Add,
+        _ => CompoundOperator::Subtract,
+    };
+    let token = tokens.next();
+    check_assignment_target(&expression, token.line)?;
+
+    Ok(Expression::CompoundAssignment(
+        Box::new(expression),
+        operator,
+        Box::new(Expression::Integer(1)),
+    ))
+}
+
+/// Whether the next tokens are a `++` or `--` ending the statement, or the
+/// increment of a `for` loop. They are scanned as two operators, so that
+/// `--x` and `a--b` keep meaning `-(-x)` and `a - (-b)`.
+fn is_postfix_step(tokens: &mut Tokens) -> bool {
+    let operator = tokens.peek_type();
+
+    matches!(operator, TokenType::Plus | TokenType::Minus)
+        && tokens.peek_type_after(1) == operator
+        && matches!(
+            tokens.peek_type_after(2),
+            TokenType::Semicolon | TokenType::RightParen
+        )
+}
+
+fn check_assignment_target(target: &Expression, line: Line) -> Result<(), LoxError> {
+    match target {
+        Expression::Variable(_) | Expression::Index(_, _) => Ok(()),
+        _ => Err(LoxError {
+            line,
+            message: "Invalid assignment target.".to_owned(),
+        }),
+    }
+}
+
+/// `condition ? then : else`, right-associative so that conditionals chain.
+fn parse_conditional(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let condition = parse_or(tokens)?;
 
-    if tokens.consume(TokenType::Or).is_ok() {
-        let right = parse_or(tokens)?;
+    if tokens.consume(TokenType::Question).is_ok() {
+        let then_expression = parse_expression(tokens)?;
+        tokens.consume(TokenType::Colon)?;
+        let else_expression = parse_conditional(tokens)?;
 
-        Ok(Expression::Or(Box::new(left), Box::new(right)))
+        Ok(Expression::Conditional(
+            Box::new(condition),
+            Box::new(then_expression),
+            Box::new(else_expression),
+        ))
     } else {
-        Ok(left)
+        Ok(condition)
     }
 }
 
-fn parse_and(tokens: &mut Tokens) -> Result<Expression, LoxError> {
-    let left = parse_equality(tokens)?;
+fn parse_or(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let mut left = parse_and(tokens)?;
 
-    if tokens.consume(TokenType::And).is_ok() {
+    while tokens.consume(TokenType::Or).is_ok() {
         let right = parse_and(tokens)?;
+        left = Expression::Or(Box::new(left), Box::new(right));
+    }
 
-        Ok(Expression::And(Box::new(left), Box::new(right)))
-    } else {
-        Ok(left)
+    Ok(left)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let mut left = parse_equality(tokens)?;
+
+    while tokens.consume(TokenType::And).is_ok() {
+        let right = parse_equality(tokens)?;
+        left = Expression::And(Box::new(left), Box::new(right));
     }
+
+    Ok(left)
 }
 
 fn parse_equality(tokens: &mut Tokens) -> Result<Expression, LoxError> {
-    let left = parse_comparison(tokens)?;
+    let mut left = parse_comparison(tokens)?;
 
-    match tokens.peek_type() {
-        TokenType::BangEqual => {
-            tokens.next();
+    loop {
+        left = match tokens.peek_type() {
+            TokenType::BangEqual => {
+                tokens.next();
 
-            let right = parse_equality(tokens)?;
-            Ok(Expression::NotEqual(Box::new(left), Box::new(right)))
-        }
-        TokenType::EqualEqual => {
-            tokens.next();
+                let right = parse_comparison(tokens)?;
+                Expression::NotEqual(Box::new(left), Box::new(right))
+            }
+            TokenType::EqualEqual => {
+                tokens.next();
 
-            let right = parse_equality(tokens)?;
-            Ok(Expression::Equal(Box::new(left), Box::new(right)))
-        }
-        _ => Ok(left),
+                let right = parse_comparison(tokens)?;
+                Expression::Equal(Box::new(left), Box::new(right))
+            }
+            _ => return Ok(left),
+        };
     }
 }
 
 fn parse_comparison(tokens: &mut Tokens) -> Result<Expression, LoxError> {
-    let left = parse_term(tokens)?;
+    let mut left = parse_bit_or(tokens)?;
 
-    match tokens.peek_type() {
-        TokenType::LessEqual => {
-            tokens.next();
+    loop {
+        left = match tokens.peek_type() {
+            TokenType::LessEqual => {
+                tokens.next();
 
-            let right = parse_comparison(tokens)?;
-            Ok(Expression::LessEqual(Box::new(left), Box::new(right)))
-        }
-        TokenType::Less => {
-            tokens.next();
+                let right = parse_bit_or(tokens)?;
+                Expression::LessEqual(Box::new(left), Box::new(right))
+            }
+            TokenType::Less => {
+                tokens.next();
 
-            let right = parse_comparison(tokens)?;
-            Ok(Expression::Less(Box::new(left), Box::new(right)))
-        }
-        TokenType::GreaterEqual => {
-            tokens.next();
+                let right = parse_bit_or(tokens)?;
+                Expression::Less(Box::new(left), Box::new(right))
+            }
+            TokenType::GreaterEqual => {
+                tokens.next();
 
-            let right = parse_comparison(tokens)?;
-            Ok(Expression::GreaterEqual(Box::new(left), Box::new(right)))
-        }
-        TokenType::Greater => {
-            tokens.next();
+                let right = parse_bit_or(tokens)?;
+                Expression::GreaterEqual(Box::new(left), Box::new(right))
+            }
+            TokenType::Greater => {
+                tokens.next();
 
-            let right = parse_comparison(tokens)?;
-            Ok(Expression::Greater(Box::new(left), Box::new(right)))
-        }
-        _ => Ok(left),
+                let right = parse_bit_or(tokens)?;
+                Expression::Greater(Box::new(left), Box::new(right))
+            }
+            _ => return Ok(left),
+        };
+    }
+}
+
+fn parse_bit_or(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let mut left = parse_bit_xor(tokens)?;
+
+    while tokens.consume(TokenType::Pipe).is_ok() {
+        let right = parse_bit_xor(tokens)?;
+        left = Expression::BitOr(Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_bit_xor(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let mut left = parse_bit_and(tokens)?;
+
+    while tokens.consume(TokenType::Caret).is_ok() {
+        let right = parse_bit_and(tokens)?;
+        left = Expression::BitXor(Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_bit_and(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let mut left = parse_shift(tokens)?;
+
+    while tokens.consume(TokenType::Ampersand).is_ok() {
+        let right = parse_shift(tokens)?;
+        left = Expression::BitAnd(Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_shift(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let mut left = parse_term(tokens)?;
+
+    loop {
+        left = match tokens.peek_type() {
+            TokenType::LessLess => {
+                tokens.next();
+
+                let right = parse_term(tokens)?;
+                Expression::ShiftLeft(Box::new(left), Box::new(right))
+            }
+            TokenType::GreaterGreater => {
+                tokens.next();
+
+                let right = parse_term(tokens)?;
+                Expression::ShiftRight(Box::new(left), Box::new(right))
+            }
+            _ => return Ok(left),
+        };
     }
 }
 
 fn parse_term(tokens: &mut Tokens) -> Result<Expression, LoxError> {
-    let left = parse_factor(tokens)?;
+    let mut left = parse_factor(tokens)?;
 
-    match tokens.peek_type() {
-        TokenType::Minus => {
-            tokens.next();
+    loop {
+        left = match tokens.peek_type() {
+            _ if is_postfix_step(tokens) => return Ok(left),
+            TokenType::Minus => {
+                tokens.next();
 
-            let right = parse_term(tokens)?;
-            Ok(Expression::Minus(Box::new(left), Box::new(right)))
-        }
-        TokenType::Plus => {
-            tokens.next();
+                let right = parse_factor(tokens)?;
+                Expression::Minus(Box::new(left), Box::new(right))
+            }
+            TokenType::Plus => {
+                tokens.next();
 
-            let right = parse_term(tokens)?;
-            Ok(Expression::Plus(Box::new(left), Box::new(right)))
-        }
-        _ => Ok(left),
+                let right = parse_factor(tokens)?;
+                Expression::Plus(Box::new(left), Box::new(right))
+            }
+            _ => return Ok(left),
+        };
     }
 }
 
 fn parse_factor(tokens: &mut Tokens) -> Result<Expression, LoxError> {
-    let left = parse_unary(tokens)?;
+    let mut left = parse_unary(tokens)?;
 
-    match tokens.peek_type() {
-        TokenType::Slash => {
-            tokens.next();
+    loop {
+        left = match tokens.peek_type() {
+            TokenType::Slash => {
+                tokens.next();
 
-            let right = parse_factor(tokens)?;
-            Ok(Expression::Divide(Box::new(left), Box::new(right)))
-        }
-        TokenType::Star => {
-            tokens.next();
+                let right = parse_unary(tokens)?;
+                Expression::Divide(Box::new(left), Box::new(right))
+            }
+            TokenType::Star => {
+                tokens.next();
 
-            let right = parse_factor(tokens)?;
-            Ok(Expression::Multiply(Box::new(left), Box::new(right)))
-        }
-        _ => Ok(left),
+                let right = parse_unary(tokens)?;
+                Expression::Multiply(Box::new(left), Box::new(right))
+            }
+            TokenType::Percent => {
+                tokens.next();
+
+                let right = parse_unary(tokens)?;
+                Expression::Modulo(Box::new(left), Box::new(right))
+            }
+            TokenType::TildeSlash => {
+                tokens.next();
+
+                let right = parse_unary(tokens)?;
+                Expression::FloorDivide(Box::new(left), Box::new(right))
+            }
+            _ => return Ok(left),
+        };
     }
 }
 
@@ -156,39 +324,86 @@ fn parse_unary(tokens: &mut Tokens) -> Result<Expression, LoxError> {
             tokens.next();
 
             let expression = parse_unary(tokens)?;
-            let zero = Expression::Number(0.);
+            let zero = Expression::Integer(0);
             Ok(Expression::Minus(Box::new(zero), Box::new(expression)))
         }
-        _ => parse_call(tokens),
+        TokenType::Tilde => {
+            tokens.next();
+
+            let expression = parse_unary(tokens)?;
+            Ok(Expression::BitNot(Box::new(expression)))
+        }
+        _ => parse_power(tokens),
+    }
+}
+
+/// `**` binds tighter than unary operators on its left, so that `-2 ** 2`
+/// is `-4`, and is right-associative: `2 ** 3 ** 2` is `2 ** 9`.
+fn parse_power(tokens: &mut Tokens) -> Result<Expression, LoxError> {
+    let base = parse_call(tokens)?;
+
+    if tokens.consume(TokenType::StarStar).is_ok() {
+        let exponent = parse_unary(tokens)?;
+
+        Ok(Expression::Power(Box::new(base), Box::new(exponent)))
+    } else {
+        Ok(base)
     }
 }
 
 fn parse_call(tokens: &mut Tokens) -> Result<Expression, LoxError> {
     let mut expression = parse_primary(tokens)?;
 
-    while tokens.consume(TokenType::LeftParen).is_ok() {
-        let arguments = match tokens.consume(TokenType::RightParen) {
-            Ok(_) => Ok(vec![]),
-            Err(_) => {
-                let args = parse_args(tokens)?;
-                tokens.consume(TokenType::RightParen)?;
-                Ok(args)
+    loop {
+        if tokens.consume(TokenType::LeftParen).is_ok() {
+            let arguments = match tokens.consume(TokenType::RightParen) {
+                Ok(_) => Ok(vec![]),
+                Err(_) => {
+                    let args = parse_args(tokens)?;
+                    tokens.consume(TokenType::RightParen)?;
+                    Ok(args)
+                }
+            }?;
+
+            expression = Expression::Call(Box::new(expression), arguments);
+        } else if tokens.consume(TokenType::LeftBracket).is_ok() {
+            let index = parse_expression(tokens)?;
+            tokens.consume(TokenType::RightBracket)?;
+
+            expression = Expression::Index(Box::new(expression), Box::new(index));
+        } else if tokens.consume(TokenType::Dot).is_ok() {
+            if let TokenType::Identifier(name) = tokens.peek_type() {
+                tokens.next();
+                expression = Expression::Get(Box::new(expression), name);
+            } else {
+                let token = tokens.peek();
+                return Err(LoxError {
+                    line: token.line,
+                    message: format!("Expected property name, got {} instead.", token.lexeme),
+                });
             }
-        }?;
-
-        expression = Expression::Call(Box::new(expression), arguments);
+        } else {
+            return Ok(expression);
+        }
     }
-
-    Ok(expression)
 }
 
-fn parse_args(tokens: &mut Tokens) -> Result<Vec<Expression>, LoxError> {
-    let expression = parse_expression(tokens)?;
-
-    let mut args = vec![expression];
+fn parse_args(tokens: &mut Tokens) -> Result<Vec<Argument>, LoxError> {
+    let mut args = vec![parse_argument(tokens)?];
     while tokens.consume(TokenType::Comma).is_ok() {
-        let expression = parse_expression(tokens)?;
-        args.push(expression);
+        let line = tokens.peek().line;
+        let argument = parse_argument(tokens)?;
+
+        if matches!(argument, Argument::Positional(_))
+            && matches!(args.last(), Some(Argument::Named(_, _)))
+        {
+            return Err(LoxError {
+                line,
+                message: "Positional arguments can't follow named arguments.".to_owned(),
+            });
+        }
+
+        args.push(argument);
     }
 
     if args.len() >= 255 {
@@ -197,15 +412,32 @@ fn parse_args(tokens: &mut Tokens) -> Result<Vec<Expression>, LoxError> {
         // We want to report the error, not throw it
         Err(LoxError {
             line: token.line,
-            message: format!("Can't have more than 255 arguments."),
+            message: "Can't have more than 255 arguments.".to_owned(),
         })
     } else {
         Ok(args)
     }
 }
 
+/// Parses `name: value` as a named argument, anything else as a positional one.
+fn parse_argument(tokens: &mut Tokens) -> Result<Argument, LoxError> {
+    match tokens.peek_type() {
+        TokenType::Identifier(name) if tokens.peek_type_after(1) == TokenType::Colon => {
+            tokens.next();
+            tokens.next();
+
+            Ok(Argument::Named(name, parse_expression(tokens)?))
+        }
+        _ => Ok(Argument::Positional(parse_expression(tokens)?)),
+    }
+}
+
 fn parse_primary(tokens: &mut Tokens) -> Result<Expression, LoxError> {
     match tokens.peek_type() {
+        TokenType::Integer(n) => {
+            tokens.next();
+            Ok(Expression::Integer(n))
+        }
         TokenType::Number(n) => {
             tokens.next();
             Ok(Expression::Number(n))
@@ -214,6 +446,35 @@ fn parse_primary(tokens: &mut Tokens) -> Result<Expression, LoxError> {
             tokens.next();
             Ok(Expression::String(s.clone()))
         }
+        TokenType::Interpolation(parts) => {
+            tokens.next();
+
+            let parts = parts
+                .into_iter()
+                .map(|part| match part {
+                    StringPart::Literal(s) => Ok(Expression::String(s)),
+                    StringPart::Expression(embedded) => {
+                        let mut embedded = Tokens::new(embedded);
+                        let expression = parse_expression(&mut embedded)?;
+
+                        let token = embedded.peek();
+                        if token.token_type == TokenType::Eof {
+                            Ok(expression)
+                        } else {
+                            Err(LoxError {
+                                line: token.line,
+                                message: format!(
+                                    "Expected '}}' after interpolated expression, got '{}' instead",
+                                    token.lexeme
+                                ),
+                            })
+                        }
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+
+            Ok(Expression::Interpolation(parts))
+        }
         TokenType::True => {
             tokens.next();
             Ok(Expression::True)
@@ -226,6 +487,28 @@ fn parse_primary(tokens: &mut Tokens) -> Result<Expression, LoxError> {
             tokens.next();
             Ok(Expression::Nil)
         }
+        TokenType::Fun => {
+            tokens.next();
+
+            let parameters = parse_parameter_list(tokens)?;
+            let body = parse_function_body(tokens)?;
+
+            Ok(Expression::Function(parameters, Box::new(body)))
+        }
+        TokenType::LeftParen if is_arrow_function(tokens) => {
+            let parameters = parse_parameter_list(tokens)?;
+            tokens.consume(TokenType::Arrow)?;
+
+            let body = if tokens.peek_type() == TokenType::LeftBrace {
+                parse_function_body(tokens)?
+            } else {
+                let line = tokens.peek().line;
+                let expression = parse_expression(tokens)?;
+                Declaration::Block(vec![Declaration::Return(expression, line)])
+            };
+
+            Ok(Expression::Function(parameters, Box::new(body)))
+        }
         TokenType::LeftParen => {
             tokens.next();
 
@@ -239,6 +522,46 @@ fn parse_primary(tokens: &mut Tokens) -> Result<Expression, LoxError> {
             tokens.next();
             Ok(Expression::Variable(id))
         }
+        TokenType::LeftBracket => {
+            tokens.next();
+
+            let mut elements = vec![];
+            while tokens.consume(TokenType::RightBracket).is_err() {
+                elements.push(parse_expression(tokens)?);
+
+                if tokens.consume(TokenType::Comma).is_err() {
+                    tokens.consume(TokenType::RightBracket)?;
+                    break;
+                }
+            }
+
+            Ok(Expression::List(elements))
+        }
+        // Statements starting with a brace are blocks, so a brace only starts
+        // a map when an expression is expected.
+        TokenType::LeftBrace => {
+            tokens.next();
+
+            let mut entries = vec![];
+            while tokens.consume(TokenType::RightBrace).is_err() {
+                let key = parse_expression(tokens)?;
+                tokens.consume(TokenType::Colon)?;
+                let value = parse_expression(tokens)?;
+                entries.push((key, value));
+
+                if tokens.consume(TokenType::Comma).is_err() {
+                    tokens.consume(TokenType::RightBrace)?;
+                    break;
+                }
+            }
+
+            Ok(Expression::Map(entries))
+        }
+        TokenType::Await => Err(LoxError {
+            line: tokens.peek().line,
+            message: "await can only start a statement, a variable initializer or an assignment."
+                .to_owned(),
+        }),
         _ => {
             let token = tokens.peek();
             Err(LoxError {
@@ -248,3 +571,14 @@ fn parse_primary(tokens: &mut Tokens) -> Result<Expression, LoxError> {
         }
     }
 }
+
+/// Looks ahead for `(a, b) =>`, telling arrow functions apart from
+/// parenthesized expressions.
+fn is_arrow_function(tokens: &mut Tokens) -> bool {
+    let position = tokens.position();
+    let is_arrow_function =
+        parse_parameter_list(tokens).is_ok() && tokens.peek_type() == TokenType::Arrow;
+    tokens.rewind(position);
+
+    is_arrow_function
+}
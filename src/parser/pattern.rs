@@ -0,0 +1,139 @@
+use crate::{
+    error::LoxError,
+    grammar::{Expression, Pattern},
+    token::{TokenType, Tokens},
+};
+
+pub fn parse_pattern(tokens: &mut Tokens) -> Result<Pattern, LoxError> {
+    let token = tokens.peek();
+
+    match token.token_type {
+        TokenType::Identifier(name) if name == "_" => {
+            tokens.next();
+            Ok(Pattern::Wildcard)
+        }
+        TokenType::Identifier(enum_name) if tokens.peek_type_after(1) == TokenType::Dot => {
+            tokens.next();
+            tokens.next();
+            let variant = parse_name(tokens)?;
+
+            let fields = if tokens.consume(TokenType::LeftParen).is_ok() {
+                let fields = parse_patterns(tokens, TokenType::RightParen)?;
+                tokens.consume(TokenType::RightParen)?;
+                Some(fields)
+            } else {
+                None
+            };
+
+            Ok(Pattern::Variant(enum_name, variant, fields))
+        }
+        TokenType::Identifier(name) => {
+            tokens.next();
+            Ok(Pattern::Binding(name))
+        }
+        TokenType::LeftBracket => {
+            tokens.next();
+            parse_list_pattern(tokens)
+        }
+        TokenType::Integer(n) => {
+            tokens.next();
+            Ok(Pattern::Literal(Expression::Integer(n)))
+        }
+        TokenType::Number(n) => {
+            tokens.next();
+            Ok(Pattern::Literal(Expression::Number(n)))
+        }
+        TokenType::String(s) => {
+            tokens.next();
+            Ok(Pattern::Literal(Expression::String(s)))
+        }
+        TokenType::True => {
+            tokens.next();
+            Ok(Pattern::Literal(Expression::True))
+        }
+        TokenType::False => {
+            tokens.next();
+            Ok(Pattern::Literal(Expression::False))
+        }
+        TokenType::Nil => {
+            tokens.next();
+            Ok(Pattern::Literal(Expression::Nil))
+        }
+        TokenType::Minus => {
+            tokens.next();
+
+            let number = match tokens.peek_type() {
+                TokenType::Integer(n) => Expression::Integer(n),
+                TokenType::Number(n) => Expression::Number(n),
+                _ => return Err(expected_pattern(tokens)),
+            };
+            tokens.next();
+
+            let zero = Expression::Integer(0);
+            Ok(Pattern::Literal(Expression::Minus(
+                Box::new(zero),
+                Box::new(number),
+            )))
+        }
+        _ => Err(expected_pattern(tokens)),
+    }
+}
+
+/// `[a, b, ...rest]`, after the opening bracket.
+fn parse_list_pattern(tokens: &mut Tokens) -> Result<Pattern, LoxError> {
+    let mut items = vec![];
+    let mut rest = None;
+
+    while tokens.consume(TokenType::RightBracket).is_err() {
+        if tokens.consume(TokenType::Ellipsis).is_ok() {
+            rest = Some(parse_name(tokens)?);
+            tokens.consume(TokenType::RightBracket)?;
+            break;
+        }
+
+        items.push(parse_pattern(tokens)?);
+
+        if tokens.consume(TokenType::Comma).is_err() {
+            tokens.consume(TokenType::RightBracket)?;
+            break;
+        }
+    }
+
+    Ok(Pattern::List(items, rest))
+}
+
+/// Comma separated patterns, up to the closing token.
+fn parse_patterns(tokens: &mut Tokens, closing: TokenType) -> Result<Vec<Pattern>, LoxError> {
+    let mut patterns = vec![];
+
+    while tokens.peek_type() != closing {
+        patterns.push(parse_pattern(tokens)?);
+
+        if tokens.consume(TokenType::Comma).is_err() {
+            break;
+        }
+    }
+
+    Ok(patterns)
+}
+
+fn parse_name(tokens: &mut Tokens) -> Result<String, LoxError> {
+    let token = tokens.next();
+
+    match token.token_type {
+        TokenType::Identifier(name) => Ok(name),
+        _ => Err(LoxError {
+            line: token.line,
+            message: format!("Expected identifier, got {} instead.", token.lexeme),
+        }),
+    }
+}
+
+fn expected_pattern(tokens: &mut Tokens) -> LoxError {
+    let token = tokens.peek();
+
+    LoxError {
+        line: token.line,
+        message: format!("Expected pattern, got {} instead.", token.lexeme),
+    }
+}
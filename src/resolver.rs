@@ -0,0 +1,486 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    error::{LoxError, Report},
+    grammar::{AwaitTarget, Declaration, Enum, Expression, Imported, MatchArm, Parameter, Pattern},
+    types::Line,
+};
+
+/// Static checks run on the whole program before it is interpreted.
+pub fn resolve(declarations: &[Declaration]) -> Result<(), Report> {
+    let mut resolver = Resolver {
+        loops: 0,
+        functions: 0,
+        generator: false,
+        enums: HashMap::new(),
+        scopes: vec![HashMap::new()],
+        line: 0,
+        report: Report::new(),
+    };
+
+    // Top-level enums can be matched on before they are declared, in functions.
+    for declaration in declarations {
+        let declaration = match declaration {
+            Declaration::Documented(_, declaration) => declaration,
+            declaration => declaration,
+        };
+        if let Declaration::Enum(definition, _) = declaration {
+            resolver.declare_enum(definition);
+        }
+    }
+
+    for declaration in declarations {
+        // Imports are only checked here, at the top level of the file.
+        match declaration {
+            Declaration::Import(_, Imported::Module(name), line) => {
+                resolver.declare(name, false, *line)
+            }
+            Declaration::Import(_, Imported::Names(names), line) => {
+                for name in names {
+                    resolver.declare(name, false, *line);
+                }
+            }
+            _ => resolver.resolve_declaration(declaration),
+        }
+    }
+
+    if resolver.report.is_empty() {
+        Ok(())
+    } else {
+        Err(resolver.report)
+    }
+}
+
+struct Resolver {
+    /// Number of loops enclosing the current declaration, inside the current function.
+    loops: usize,
+    /// Number of functions enclosing the current declaration.
+    functions: usize,
+    /// Whether the current declaration is in the body of a generator.
+    generator: bool,
+    /// Enums declared so far, by name, whose variants patterns are checked
+    /// against.
+    enums: HashMap<String, Rc<Enum>>,
+    /// Names declared in each enclosing scope, innermost last, mapped to
+    /// whether they are constants.
+    scopes: Vec<HashMap<String, bool>>,
+    /// Line of the statement being resolved, for the errors found in its
+    /// expressions.
+    line: Line,
+    report: Report,
+}
+
+impl Resolver {
+    fn resolve_declaration(&mut self, declaration: &Declaration) {
+        if let Some(line) = declaration.line() {
+            self.line = line;
+        }
+
+        match declaration {
+            Declaration::Expression(expression, _)
+            | Declaration::Print(expression, _)
+            | Declaration::Throw(expression, _) => self.resolve_expression(expression),
+            Declaration::Return(expression, line) => {
+                if self.functions == 0 {
+                    self.report.push(LoxError {
+                        line: *line,
+                        message: "Can't return from top-level code.".to_owned(),
+                    });
+                }
+                self.resolve_expression(expression);
+            }
+            Declaration::Var(name, initializer, line) => {
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.declare(name, false, *line);
+            }
+            Declaration::Const(name, initializer, line) => {
+                self.resolve_expression(initializer);
+                self.declare(name, true, *line);
+            }
+            Declaration::Block(declarations) => {
+                self.begin_scope();
+                for declaration in declarations {
+                    self.resolve_declaration(declaration);
+                }
+                self.end_scope();
+            }
+            Declaration::If(condition, if_statement, else_statement, _) => {
+                self.resolve_expression(condition);
+                self.resolve_declaration(if_statement);
+                if let Some(else_statement) = else_statement {
+                    self.resolve_declaration(else_statement);
+                }
+            }
+            Declaration::While(condition, body, increment, _) => {
+                self.resolve_expression(condition);
+                if let Some(increment) = increment {
+                    self.resolve_expression(increment);
+                }
+                self.resolve_loop(body);
+            }
+            Declaration::ForIn(name, iterable, body, line) => {
+                self.resolve_expression(iterable);
+                self.begin_scope();
+                self.declare(name, false, *line);
+                self.resolve_loop(body);
+                self.end_scope();
+            }
+            Declaration::Function(name, parameters, body, line) => {
+                self.declare(name, false, *line);
+                self.resolve_function(parameters, body);
+            }
+            Declaration::Documented(_, declaration) => self.resolve_declaration(declaration),
+            Declaration::Try(body, catch, finally) => {
+                self.resolve_declaration(body);
+                if let Some((name, handler)) = catch {
+                    self.begin_scope();
+                    self.bind_local(name);
+                    self.resolve_declaration(handler);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.resolve_declaration(finally);
+                }
+            }
+            Declaration::Yield(expression, line) => {
+                if self.functions == 0 {
+                    self.report.push(LoxError {
+                        line: *line,
+                        message: "Can't yield from top-level code.".to_owned(),
+                    });
+                }
+                self.resolve_expression(expression);
+            }
+            Declaration::Await(target, expression, line) => {
+                // Generators are resumed by their caller, which can't wait.
+                if self.generator {
+                    self.report.push(LoxError {
+                        line: *line,
+                        message: "Can't await inside a generator.".to_owned(),
+                    });
+                }
+                self.resolve_expression(expression);
+                match target {
+                    AwaitTarget::Discard => {}
+                    AwaitTarget::Var(name) => self.declare(name, false, *line),
+                    AwaitTarget::Assign(name) => self.check_assignable(name),
+                }
+            }
+            Declaration::Enum(definition, line) => {
+                self.declare(&definition.name, false, *line);
+                self.declare_enum(definition);
+            }
+            Declaration::Match(subject, arms, line) => {
+                self.resolve_expression(subject);
+                for arm in arms {
+                    self.begin_scope();
+                    self.resolve_pattern(&arm.pattern, *line);
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expression(guard);
+                    }
+                    self.resolve_declaration(&arm.body);
+                    self.end_scope();
+                }
+                self.check_exhaustive(arms, *line);
+            }
+            Declaration::Generator(body) => {
+                self.generator = true;
+                self.resolve_declaration(body);
+            }
+            Declaration::Import(_, _, line) => self.report.push(LoxError {
+                line: *line,
+                message: "Imports are only allowed at the top level of a file.".to_owned(),
+            }),
+            Declaration::Break(line) => self.check_in_loop(*line, "break"),
+            Declaration::Continue(line) => self.check_in_loop(*line, "continue"),
+        }
+    }
+
+    fn resolve_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Integer(_)
+            | Expression::Number(_)
+            | Expression::String(_)
+            | Expression::True
+            | Expression::False
+            | Expression::Nil
+            | Expression::Variable(_) => {}
+            Expression::Not(expression)
+            | Expression::BitNot(expression)
+            | Expression::Paren(expression)
+            | Expression::Get(expression, _) => self.resolve_expression(expression),
+            Expression::Assignment(name, expression) => {
+                self.resolve_expression(expression);
+                self.check_assignable(name);
+            }
+            Expression::Equal(left, right)
+            | Expression::NotEqual(left, right)
+            | Expression::Less(left, right)
+            | Expression::LessEqual(left, right)
+            | Expression::Greater(left, right)
+            | Expression::GreaterEqual(left, right)
+            | Expression::Plus(left, right)
+            | Expression::Minus(left, right)
+            | Expression::Multiply(left, right)
+            | Expression::Divide(left, right)
+            | Expression::FloorDivide(left, right)
+            | Expression::Modulo(left, right)
+            | Expression::Power(left, right)
+            | Expression::BitAnd(left, right)
+            | Expression::BitOr(left, right)
+            | Expression::BitXor(left, right)
+            | Expression::ShiftLeft(left, right)
+            | Expression::ShiftRight(left, right)
+            | Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Index(left, right) => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expression::CompoundAssignment(target, _, value) => {
+                self.resolve_expression(target);
+                self.resolve_expression(value);
+                if let Expression::Variable(name) = target.as_ref() {
+                    self.check_assignable(name);
+                }
+            }
+            Expression::IndexAssignment(collection, index, value)
+            | Expression::Conditional(collection, index, value) => {
+                self.resolve_expression(collection);
+                self.resolve_expression(index);
+                self.resolve_expression(value);
+            }
+            Expression::Call(callee, arguments) => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument.value());
+                }
+            }
+            Expression::List(elements) | Expression::Interpolation(elements) => {
+                for element in elements {
+                    self.resolve_expression(element);
+                }
+            }
+            Expression::Map(entries) => {
+                for (key, value) in entries {
+                    self.resolve_expression(key);
+                    self.resolve_expression(value);
+                }
+            }
+            Expression::Function(parameters, body) => self.resolve_function(parameters, body),
+        }
+    }
+
+    fn resolve_loop(&mut self, body: &Declaration) {
+        self.loops += 1;
+        self.resolve_declaration(body);
+        self.loops -= 1;
+    }
+
+    fn resolve_function(&mut self, parameters: &[Parameter], body: &Declaration) {
+        let (loops, generator, line) = (self.loops, self.generator, self.line);
+        self.loops = 0;
+        self.generator = false;
+        self.functions += 1;
+        self.begin_scope();
+        for parameter in parameters {
+            if let Some(default) = &parameter.default {
+                self.resolve_expression(default);
+            }
+            self.bind_local(&parameter.name);
+        }
+        self.resolve_declaration(body);
+        self.end_scope();
+        self.functions -= 1;
+        self.loops = loops;
+        self.generator = generator;
+        self.line = line;
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Records a name in the innermost scope, where a constant can't be
+    /// declared again.
+    fn declare(&mut self, name: &str, constant: bool, line: Line) {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("The global scope is never popped");
+
+        if scope.get(name) == Some(&true) {
+            self.report.push(LoxError {
+                line,
+                message: format!("Can't redeclare constant {}.", name),
+            });
+        } else {
+            scope.insert(name.to_owned(), constant);
+        }
+    }
+
+    /// Records a name in the fresh scope of a function or a catch clause,
+    /// where nothing can clash with it.
+    fn bind_local(&mut self, name: &str) {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("The global scope is never popped");
+        scope.insert(name.to_owned(), false);
+    }
+
+    /// Reports an assignment to a name that resolves to a constant.
+    fn check_assignable(&mut self, name: &str) {
+        let constant = self.scopes.iter().rev().find_map(|scope| scope.get(name));
+
+        if constant == Some(&true) {
+            self.report.push(LoxError {
+                line: self.line,
+                message: format!("Can't assign to constant {}.", name),
+            });
+        }
+    }
+
+    fn declare_enum(&mut self, definition: &Rc<Enum>) {
+        self.enums
+            .insert(definition.name.clone(), definition.clone());
+    }
+
+    /// Checks the variants of known enums, and that no name is bound twice.
+    fn resolve_pattern(&mut self, pattern: &Pattern, line: Line) {
+        let mut bindings = vec![];
+        self.resolve_subpattern(pattern, &mut bindings, line);
+    }
+
+    fn resolve_subpattern(&mut self, pattern: &Pattern, bindings: &mut Vec<String>, line: Line) {
+        match pattern {
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+            Pattern::Binding(name) => self.bind(name, bindings, line),
+            Pattern::Variant(enum_name, variant_name, patterns) => {
+                if let Some(definition) = self.enums.get(enum_name) {
+                    let variant = definition
+                        .variants
+                        .iter()
+                        .find(|variant| variant.name == *variant_name);
+
+                    let message = match (variant, patterns) {
+                        (None, _) => Some(format!(
+                            "Enum {} has no variant {}.",
+                            enum_name, variant_name
+                        )),
+                        (Some(variant), Some(patterns)) => match &variant.fields {
+                            Some(fields) if fields.len() == patterns.len() => None,
+                            Some(fields) => Some(format!(
+                                "Variant {}.{} has {} fields, not {}.",
+                                enum_name,
+                                variant_name,
+                                fields.len(),
+                                patterns.len()
+                            )),
+                            None => Some(format!(
+                                "Variant {}.{} has no fields.",
+                                enum_name, variant_name
+                            )),
+                        },
+                        (Some(_), None) => None,
+                    };
+
+                    if let Some(message) = message {
+                        self.report.push(LoxError { line, message });
+                    }
+                }
+
+                for pattern in patterns.iter().flatten() {
+                    self.resolve_subpattern(pattern, bindings, line);
+                }
+            }
+            Pattern::List(patterns, rest) => {
+                for pattern in patterns {
+                    self.resolve_subpattern(pattern, bindings, line);
+                }
+                if let Some(rest) = rest {
+                    self.bind(rest, bindings, line);
+                }
+            }
+        }
+    }
+
+    fn bind(&mut self, name: &String, bindings: &mut Vec<String>, line: Line) {
+        if bindings.contains(name) {
+            self.report.push(LoxError {
+                line,
+                message: format!("{} is bound twice in the same pattern.", name),
+            });
+        } else {
+            bindings.push(name.clone());
+            self.declare(name, false, line);
+        }
+    }
+
+    /// Reports the variants left out by a match on a known enum, which is
+    /// the enum of the first variant pattern among its arms. Guarded arms
+    /// may not match, so they don't cover their variant. Other matches can't
+    /// be checked, and raise a runtime error when no arm matches.
+    fn check_exhaustive(&mut self, arms: &[MatchArm], line: Line) {
+        let patterns: Vec<&Pattern> = arms
+            .iter()
+            .filter(|arm| arm.guard.is_none())
+            .map(|arm| &arm.pattern)
+            .collect();
+        if patterns.iter().any(|pattern| pattern.is_irrefutable()) {
+            return;
+        }
+
+        let Some(enum_name) = arms.iter().find_map(|arm| match &arm.pattern {
+            Pattern::Variant(enum_name, _, _) => Some(enum_name),
+            _ => None,
+        }) else {
+            return;
+        };
+        let Some(definition) = self.enums.get(enum_name) else {
+            return;
+        };
+
+        let missing: Vec<&str> = definition
+            .variants
+            .iter()
+            .filter(|variant| {
+                !patterns.iter().any(|pattern| match pattern {
+                    Pattern::Variant(other_enum, other_variant, fields) => {
+                        other_enum == enum_name
+                            && *other_variant == variant.name
+                            && fields.iter().flatten().all(|field| field.is_irrefutable())
+                    }
+                    _ => false,
+                })
+            })
+            .map(|variant| variant.name.as_str())
+            .collect();
+
+        if !missing.is_empty() {
+            self.report.push(LoxError {
+                line,
+                message: format!(
+                    "Match on {} doesn't cover {}.",
+                    enum_name,
+                    missing.join(", ")
+                ),
+            });
+        }
+    }
+
+    fn check_in_loop(&mut self, line: Line, keyword: &str) {
+        if self.loops == 0 {
+            self.report.push(LoxError {
+                line,
+                message: format!("Can't use '{}' outside of a loop.", keyword),
+            });
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use crate::{
+    config::{Capability, Config},
+    event_loop::Scheduler,
+    gc::Heap,
+    module::Modules,
+    random::Random,
+    time::TimeSource,
+};
+
+/// State shared by every environment of a single run.
+pub struct Runtime {
+    pub heap: Heap,
+    pub capabilities: HashSet<Capability>,
+    pub random: RefCell<Random>,
+    pub time_source: Rc<dyn TimeSource>,
+    pub modules: RefCell<Modules>,
+    /// Tasks of the event loop, if the run has one.
+    pub scheduler: RefCell<Scheduler>,
+}
+
+impl Runtime {
+    pub fn new(config: &Config) -> Runtime {
+        Runtime {
+            heap: Heap::new(config.gc_stress, config.memory_limit),
+            capabilities: config.capabilities.clone(),
+            random: RefCell::new(match config.seed {
+                Some(seed) => Random::new(seed),
+                None => Random::from_time(),
+            }),
+            time_source: config.time_source.clone(),
+            modules: RefCell::new(Modules::default()),
+            scheduler: RefCell::new(Scheduler::default()),
+        }
+    }
+}
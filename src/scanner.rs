@@ -1,120 +1,192 @@
 use crate::{
     error::{LoxError, Report},
     source::Source,
-    token::{Token, TokenType},
+    token::{StringPart, Token, TokenType},
+    types::Line,
 };
 
-pub fn scan(source: Source) -> Result<Vec<Token>, Report> {
+pub fn scan(mut source: Source) -> Result<Vec<Token>, Report> {
     let mut tokens: Vec<Token> = Vec::new();
     let mut report = Report::new();
 
-    scan_tokens_rec(source, &mut tokens, &mut report);
+    while scan_token(&mut source, &mut tokens, &mut report) {}
 
     if report.is_empty() {
-        Ok(tokens)
+        Ok(attach_doc_comments(tokens))
     } else {
         Err(report)
     }
 }
 
-fn scan_tokens_rec(mut source: Source, tokens: &mut Vec<Token>, report: &mut Report) {
+/// Scans the next token, if any. Returns false once EOF has been pushed.
+fn scan_token(source: &mut Source, tokens: &mut Vec<Token>, report: &mut Report) -> bool {
     match source.next_char() {
-        None => tokens.push(Token {
-            token_type: TokenType::Eof,
-            lexeme: source.flush_lexeme(),
-            line: source.get_current_line(),
-        }),
+        None => {
+            tokens.push(Token {
+                token_type: TokenType::Eof,
+                lexeme: source.flush_lexeme(),
+                line: source.get_current_line(),
+                doc_comment: None,
+            });
+            false
+        }
         Some(c) => {
             let token_type = match c {
                 '(' => Some(TokenType::LeftParen),
                 ')' => Some(TokenType::RightParen),
                 '{' => Some(TokenType::LeftBrace),
                 '}' => Some(TokenType::RightBrace),
+                '[' => Some(TokenType::LeftBracket),
+                ']' => Some(TokenType::RightBracket),
                 ',' => Some(TokenType::Comma),
-                '.' => Some(TokenType::Dot),
-                '-' => Some(TokenType::Minus),
-                '+' => Some(TokenType::Plus),
+                ':' => Some(TokenType::Colon),
+                '.' => match source.maybe_next_char('.') {
+                    Some(_) => match source.maybe_next_char('.') {
+                        Some(_) => Some(TokenType::Ellipsis),
+                        None => {
+                            report.push(LoxError {
+                                message: "Unexpected '..', did you mean '...'?".to_owned(),
+                                line: source.get_current_line(),
+                            });
+                            source.flush_lexeme();
+                            None
+                        }
+                    },
+                    None => Some(TokenType::Dot),
+                },
+                '-' => match source.maybe_next_char('=') {
+                    Some(_) => Some(TokenType::MinusEqual),
+                    _ => Some(TokenType::Minus),
+                },
+                '+' => match source.maybe_next_char('=') {
+                    Some(_) => Some(TokenType::PlusEqual),
+                    _ => Some(TokenType::Plus),
+                },
+                '?' => Some(TokenType::Question),
                 ';' => Some(TokenType::Semicolon),
-                '*' => Some(TokenType::Star),
+                '*' => match source.maybe_next_char('*') {
+                    Some(_) => Some(TokenType::StarStar),
+                    _ => match source.maybe_next_char('=') {
+                        Some(_) => Some(TokenType::StarEqual),
+                        _ => Some(TokenType::Star),
+                    },
+                },
+                '%' => Some(TokenType::Percent),
+                // Floor division is spelled `~/`, as in Dart, since `//`
+                // starts a comment.
+                '~' => match source.maybe_next_char('/') {
+                    Some(_) => Some(TokenType::TildeSlash),
+                    _ => Some(TokenType::Tilde),
+                },
+                '&' => Some(TokenType::Ampersand),
+                '|' => Some(TokenType::Pipe),
+                '^' => Some(TokenType::Caret),
                 '!' => match source.maybe_next_char('=') {
                     Some(_) => Some(TokenType::BangEqual),
                     _ => Some(TokenType::Bang),
                 },
                 '=' => match source.maybe_next_char('=') {
                     Some(_) => Some(TokenType::EqualEqual),
-                    _ => Some(TokenType::Equal),
+                    _ => match source.maybe_next_char('>') {
+                        Some(_) => Some(TokenType::Arrow),
+                        _ => Some(TokenType::Equal),
+                    },
                 },
                 '<' => match source.maybe_next_char('=') {
                     Some(_) => Some(TokenType::LessEqual),
-                    _ => Some(TokenType::Less),
+                    _ => match source.maybe_next_char('<') {
+                        Some(_) => Some(TokenType::LessLess),
+                        _ => Some(TokenType::Less),
+                    },
                 },
                 '>' => match source.maybe_next_char('=') {
                     Some(_) => Some(TokenType::GreaterEqual),
-                    _ => Some(TokenType::Greater),
+                    _ => match source.maybe_next_char('>') {
+                        Some(_) => Some(TokenType::GreaterGreater),
+                        _ => Some(TokenType::Greater),
+                    },
                 },
                 '/' => match source.maybe_next_char('/') {
                     Some(_) => {
-                        source.consume_until('\n');
-                        source.flush_lexeme();
-                        None
-                    }
-                    _ => Some(TokenType::Slash),
-                },
-                '"' => {
-                    source.consume_until('"');
-                    match source.maybe_next_char('"') {
-                        Some(_) => Some(TokenType::String(
-                            source.peek_lexeme().trim_matches('"').to_owned(),
-                        )),
-                        None => {
-                            report.push(LoxError {
-                                line: source.get_current_line(),
-                                message: format!("Unterminated string {}", source.flush_lexeme()),
-                            });
-                            None
+                        source.consume_while(|c| c != '\n' && c != '\r');
+                        let comment = source.flush_lexeme();
+
+                        // `///` documents what follows, `////` is a regular comment.
+                        match comment.strip_prefix("///") {
+                            Some(doc) if !doc.starts_with('/') => {
+                                let doc = doc.strip_prefix(' ').unwrap_or(doc).trim_end();
+                                Some(TokenType::DocComment(doc.to_owned()))
+                            }
+                            _ => None,
                         }
                     }
-                }
-                c if c.is_digit(10) => {
-                    source.consume_digits();
-                    if let Some(_) = source.maybe_next_char('.') {
-                        source.consume_digits();
-                    };
-
-                    let number_string = source.peek_lexeme();
-                    let number: Result<f64, _> = number_string.parse();
-
-                    match number {
-                        Ok(number) => Some(TokenType::Number(number)),
-                        Err(_) => {
-                            report.push(LoxError {
-                                message: format!("{} is not a valid number", number_string),
-                                line: source.get_current_line(),
-                            });
+                    _ => match source.maybe_next_char('*') {
+                        Some(_) => {
+                            skip_block_comment(source, report);
                             None
                         }
-                    }
+                        _ => match source.maybe_next_char('=') {
+                            Some(_) => Some(TokenType::SlashEqual),
+                            _ => Some(TokenType::Slash),
+                        },
+                    },
+                },
+                '"' => scan_string(source, report),
+                // Raw strings, such as r"C:\path", keep backslashes as they are.
+                'r' if source.peek_char() == Some(&'"') => {
+                    source.next_char();
+                    scan_raw_string(source, report)
                 }
-                c if c.is_alphabetic() || c == '_' => {
-                    source.consume_alphanumeric();
+                c if c.is_ascii_digit() => match scan_number(source, c) {
+                    Ok(number) => Some(number),
+                    Err(reason) => {
+                        report.push(LoxError {
+                            message: format!(
+                                "{} is not a valid number, {}",
+                                source.peek_lexeme(),
+                                reason
+                            ),
+                            line: source.get_current_line(),
+                        });
+                        source.flush_lexeme();
+                        None
+                    }
+                },
+                c if unicode_ident::is_xid_start(c) || c == '_' || c == '$' => {
+                    source.consume_identifier();
                     let identifier = source.peek_lexeme();
                     match &identifier[..] {
                         "and" => Some(TokenType::And),
+                        "as" => Some(TokenType::As),
+                        "break" => Some(TokenType::Break),
+                        "catch" => Some(TokenType::Catch),
                         "class" => Some(TokenType::Class),
+                        "continue" => Some(TokenType::Continue),
                         "else" => Some(TokenType::Else),
                         "false" => Some(TokenType::False),
+                        "finally" => Some(TokenType::Finally),
                         "for" => Some(TokenType::For),
+                        "from" => Some(TokenType::From),
                         "fun" => Some(TokenType::Fun),
                         "if" => Some(TokenType::If),
+                        "import" => Some(TokenType::Import),
+                        "in" => Some(TokenType::In),
                         "nil" => Some(TokenType::Nil),
                         "or" => Some(TokenType::Or),
                         "print" => Some(TokenType::Print),
                         "return" => Some(TokenType::Return),
                         "super" => Some(TokenType::Super),
                         "this" => Some(TokenType::This),
+                        "throw" => Some(TokenType::Throw),
                         "true" => Some(TokenType::True),
+                        "try" => Some(TokenType::Try),
                         "var" => Some(TokenType::Var),
                         "while" => Some(TokenType::While),
+                        "yield" => Some(TokenType::Yield),
+                        "await" => Some(TokenType::Await),
+                        "enum" => Some(TokenType::Enum),
+                        "match" => Some(TokenType::Match),
+                        "const" => Some(TokenType::Const),
                         _ => Some(TokenType::Identifier(identifier)),
                     }
                 }
@@ -136,10 +208,297 @@ fn scan_tokens_rec(mut source: Source, tokens: &mut Vec<Token>, report: &mut Rep
                     token_type,
                     lexeme: source.flush_lexeme(),
                     line: source.get_current_line(),
+                    doc_comment: None,
                 })
             }
 
-            scan_tokens_rec(source, tokens, report);
+            true
         }
+    }
+}
+
+/// Scans a number after its first digit: `123`, `1_000`, `1.5`, `1e-9`,
+/// `0xFF` or `0b1010`. Hexadecimal and binary literals take up to 64 bits,
+/// so that `0xFFFFFFFFFFFFFFFF` is `-1`.
+fn scan_number(source: &mut Source, first: char) -> Result<TokenType, String> {
+    let radix = match (first, source.peek_char()) {
+        ('0', Some('x' | 'X')) => 16,
+        ('0', Some('b' | 'B')) => 2,
+        _ => 10,
     };
+
+    if radix != 10 {
+        source.next_char();
+        // Reading every letter reports `0b102` or `0xFG` as a whole.
+        let digits = source.consume_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        if digits.is_empty() {
+            return Err("digits are missing after the prefix".to_owned());
+        }
+
+        return u64::from_str_radix(&remove_underscores(&digits)?, radix)
+            .map(|n| TokenType::Integer(n as i64))
+            .map_err(|_| format!("it has invalid digits for base {} or is too large", radix));
+    }
+
+    let mut number = first.to_string();
+    number.push_str(&source.consume_while(|c| c.is_ascii_digit() || c == '_'));
+    let mut number = remove_underscores(&number)?;
+    let mut is_float = false;
+
+    if source.maybe_next_char('.').is_some() {
+        let fraction = source.consume_while(|c| c.is_ascii_digit() || c == '_');
+        if fraction.is_empty() {
+            return Err("digits are missing after the decimal point".to_owned());
+        }
+
+        number.push('.');
+        number.push_str(&remove_underscores(&fraction)?);
+        is_float = true;
+    }
+
+    if let Some(&e) = source.peek_char().filter(|&&c| c == 'e' || c == 'E') {
+        source.next_char();
+        number.push(e);
+        if let Some(sign) = source
+            .maybe_next_char('+')
+            .or_else(|| source.maybe_next_char('-'))
+        {
+            number.push(sign);
+        }
+
+        let exponent = source.consume_while(|c| c.is_ascii_digit() || c == '_');
+        if exponent.is_empty() {
+            return Err("digits are missing in the exponent".to_owned());
+        }
+
+        number.push_str(&remove_underscores(&exponent)?);
+        is_float = true;
+    }
+
+    if is_float {
+        number
+            .parse()
+            .map(TokenType::Number)
+            .map_err(|_| "it can't be read as a float".to_owned())
+    } else {
+        number
+            .parse()
+            .map(TokenType::Integer)
+            .map_err(|_| "it is too large for an integer".to_owned())
+    }
+}
+
+/// Underscores separate digits, as in `1_000_000`, and can't start, end or
+/// repeat in a group of digits.
+fn remove_underscores(digits: &str) -> Result<String, String> {
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        Err("underscores must be between digits".to_owned())
+    } else {
+        Ok(digits.replace('_', ""))
+    }
+}
+
+/// Skips a `/* ... */` comment, after its opening. Block comments nest, so
+/// that commenting out code containing one works.
+fn skip_block_comment(source: &mut Source, report: &mut Report) {
+    let line = source.get_current_line();
+    let mut depth = 1;
+
+    while depth > 0 {
+        match source.next_char() {
+            None => {
+                report.push(LoxError {
+                    line,
+                    message: "Unterminated block comment".to_owned(),
+                });
+                break;
+            }
+            Some('*') if source.maybe_next_char('/').is_some() => depth -= 1,
+            Some('/') if source.maybe_next_char('*').is_some() => depth += 1,
+            Some(_) => {}
+        }
+    }
+
+    source.flush_lexeme();
+}
+
+/// Moves the lines of consecutive doc comments onto the token following them.
+fn attach_doc_comments(tokens: Vec<Token>) -> Vec<Token> {
+    let mut attached = Vec::with_capacity(tokens.len());
+    let mut doc_comment: Option<String> = None;
+
+    for mut token in tokens {
+        match token.token_type {
+            TokenType::DocComment(line) => {
+                doc_comment = Some(match doc_comment {
+                    Some(doc_comment) => doc_comment + "\n" + &line,
+                    None => line,
+                });
+            }
+            _ => {
+                token.doc_comment = doc_comment.take();
+                attached.push(token);
+            }
+        }
+    }
+
+    attached
+}
+
+fn scan_string(source: &mut Source, report: &mut Report) -> Option<TokenType> {
+    let line = source.get_current_line();
+    let mut parts = vec![];
+    let mut string = String::new();
+    let mut is_valid = true;
+
+    loop {
+        match source.next_char() {
+            None => {
+                report_unterminated_string(source, report, line);
+                return None;
+            }
+            Some('"') => break,
+            Some('$') if source.peek_char() == Some(&'{') => {
+                source.next_char();
+                parts.push(StringPart::Literal(std::mem::take(&mut string)));
+                parts.push(StringPart::Expression(scan_interpolation(
+                    source, report, line,
+                )?));
+            }
+            Some('\\') => match source.next_char() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some('r') => string.push('\r'),
+                Some('0') => string.push('\0'),
+                Some('\\') => string.push('\\'),
+                Some('"') => string.push('"'),
+                Some('$') => string.push('$'),
+                Some('u') => match scan_unicode_escape(source) {
+                    Ok(c) => string.push(c),
+                    Err(message) => {
+                        report.push(LoxError {
+                            line: source.get_current_line(),
+                            message,
+                        });
+                        is_valid = false;
+                    }
+                },
+                // A backslash at the end of a line continues the string on
+                // the next one, without the line break and the indentation.
+                Some(c @ ('\n' | '\r')) => {
+                    if c == '\r' {
+                        source.maybe_next_char('\n');
+                    }
+                    while matches!(source.peek_char(), Some(' ' | '\t')) {
+                        source.next_char();
+                    }
+                }
+                Some(c) => {
+                    report.push(LoxError {
+                        line: source.get_current_line(),
+                        message: format!("Invalid escape sequence \\{}", c),
+                    });
+                    is_valid = false;
+                }
+                None => {
+                    report_unterminated_string(source, report, line);
+                    return None;
+                }
+            },
+            Some(c) => string.push(c),
+        }
+    }
+
+    if !is_valid {
+        source.flush_lexeme();
+        None
+    } else if parts.is_empty() {
+        Some(TokenType::String(string))
+    } else {
+        parts.push(StringPart::Literal(string));
+        Some(TokenType::Interpolation(parts))
+    }
+}
+
+/// Scans the tokens of a `${...}` expression embedded in a string, up to the
+/// matching closing brace, so that strings and maps can be nested inside.
+fn scan_interpolation(source: &mut Source, report: &mut Report, line: Line) -> Option<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut depth = 0;
+    // Identifiers and numbers are read from the lexeme, which must not
+    // start with the beginning of the string.
+    source.flush_lexeme();
+
+    while scan_token(source, &mut tokens, report) {
+        match tokens.last().map(|token| &token.token_type) {
+            Some(TokenType::LeftBrace) => depth += 1,
+            Some(TokenType::RightBrace) if depth == 0 => {
+                let brace = tokens.pop().expect("The brace was just scanned");
+                tokens.push(Token {
+                    token_type: TokenType::Eof,
+                    lexeme: brace.lexeme,
+                    line: brace.line,
+                    doc_comment: None,
+                });
+                return Some(attach_doc_comments(tokens));
+            }
+            Some(TokenType::RightBrace) => depth -= 1,
+            _ => {}
+        }
+    }
+
+    report.push(LoxError {
+        line,
+        message: "Unterminated interpolation in string".to_owned(),
+    });
+    None
+}
+
+/// Reads the `{1F600}` part of a `\u{1F600}` escape.
+fn scan_unicode_escape(source: &mut Source) -> Result<char, String> {
+    if source.maybe_next_char('{').is_none() {
+        return Err("Expected '{' after \\u".to_owned());
+    }
+
+    let mut digits = String::new();
+    while let Some(&c) = source.peek_char() {
+        if c == '}' || c == '"' {
+            break;
+        }
+        digits.push(c);
+        source.next_char();
+    }
+
+    if source.maybe_next_char('}').is_none() {
+        return Err(format!("Unterminated unicode escape \\u{{{}", digits));
+    }
+
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .filter(|_| (1..=6).contains(&digits.len()))
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("Invalid unicode escape \\u{{{}}}", digits))
+}
+
+fn scan_raw_string(source: &mut Source, report: &mut Report) -> Option<TokenType> {
+    let line = source.get_current_line();
+    let mut string = String::new();
+
+    loop {
+        match source.next_char() {
+            None => {
+                report_unterminated_string(source, report, line);
+                return None;
+            }
+            Some('"') => return Some(TokenType::String(string)),
+            Some(c) => string.push(c),
+        }
+    }
+}
+
+fn report_unterminated_string(source: &mut Source, report: &mut Report, line: Line) {
+    report.push(LoxError {
+        line,
+        message: format!("Unterminated string {}", source.flush_lexeme()),
+    });
 }
@@ -10,6 +10,8 @@ pub struct Source<'a> {
 
 impl Source<'_> {
     pub fn new<'a>(s: &'a str) -> Source<'a> {
+        let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+
         Source {
             iterable: s.chars().peekable(),
             pending_lexeme: String::new(),
@@ -21,7 +23,8 @@ impl Source<'_> {
         let next = self.iterable.next();
 
         if let Some(c) = next {
-            if c == '\n' {
+            // Lines end with `\n`, `\r\n` or a lone `\r`.
+            if c == '\n' || (c == '\r' && self.iterable.peek() != Some(&'\n')) {
                 self.current_line += 1;
             };
             self.pending_lexeme.push(c);
@@ -36,7 +39,7 @@ impl Source<'_> {
             _ => None,
         };
 
-        if let Some(_) = result {
+        if result.is_some() {
             self.next_char();
         };
 
@@ -47,22 +50,21 @@ impl Source<'_> {
         self.iterable.peek()
     }
 
-    pub fn consume_until(&mut self, expected: char) {
-        while self.peek_char() != Some(&expected) && self.peek_char() != None {
+    /// Consumes characters as long as they match, returning them.
+    pub fn consume_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut consumed = String::new();
+        while let Some(&c) = self.peek_char().filter(|&&c| predicate(c)) {
+            consumed.push(c);
             self.next_char();
         }
-    }
 
-    pub fn consume_digits(&mut self) {
-        while self.peek_char().map(|c| c.is_digit(10)) == Some(true) {
-            self.next_char();
-        }
+        consumed
     }
 
-    pub fn consume_alphanumeric(&mut self) {
-        while self.peek_char().map(|c| c.is_alphanumeric()) == Some(true) {
-            self.next_char();
-        }
+    /// Consumes the rest of an identifier, made of Unicode XID_Continue
+    /// characters, which include `_`, and `$`.
+    pub fn consume_identifier(&mut self) {
+        self.consume_while(|c| unicode_ident::is_xid_continue(c) || c == '$');
     }
 
     pub fn peek_lexeme(&self) -> String {
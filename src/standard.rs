@@ -1,13 +1,294 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs, mem, process};
 
-use crate::value::Value;
+use crate::{
+    config::Capability,
+    environment::Environment,
+    error::LoxRuntimeException,
+    generator::Machine,
+    map::Key,
+    value::{bind_arguments, Value},
+};
 
-pub fn clock(_: Vec<Value>) -> Value {
-    let start = SystemTime::now();
-    let since_the_epoch = start
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis();
+pub fn clock(environment: &Environment, _: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    environment.require(Capability::Clock, "clock")?;
 
-    Value::Number(since_the_epoch as f64 / 1000.)
+    Ok(Value::Number(environment.runtime().time_source.now()))
+}
+
+pub fn read_file(
+    environment: &Environment,
+    args: Vec<Value>,
+) -> Result<Value, LoxRuntimeException> {
+    environment.require(Capability::Filesystem, "readFile")?;
+
+    let path = args[0].as_str()?;
+    let content = fs::read_to_string(path).map_err(|error| {
+        LoxRuntimeException::Error(format!("Failed reading file {}: {}", path, error))
+    })?;
+    environment.allocate(content.len())?;
+
+    Ok(Value::String(content))
+}
+
+pub fn get_env(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    environment.require(Capability::Environment, "getEnv")?;
+
+    let name = args[0].as_str()?;
+    match env::var(name) {
+        Ok(value) => {
+            environment.allocate(value.len())?;
+            Ok(Value::String(value))
+        }
+        Err(_) => Ok(Value::Nil),
+    }
+}
+
+pub fn exit(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    environment.require(Capability::Process, "exit")?;
+
+    let code = args[0].as_number()?;
+    process::exit(code as i32)
+}
+
+pub fn random(environment: &Environment, _: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    environment.require(Capability::Random, "random")?;
+
+    let mut random = environment.runtime().random.borrow_mut();
+    Ok(Value::Number(random.next_f64()))
+}
+
+pub fn len(_: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    match &args[0] {
+        Value::String(s) => Ok(Value::Integer(s.chars().count() as i64)),
+        Value::Map(map) => Ok(Value::Integer(map.borrow().len() as i64)),
+        value => Ok(Value::Integer(value.as_list()?.borrow().len() as i64)),
+    }
+}
+
+pub fn push(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let list = args[0].as_list()?;
+    environment.allocate(mem::size_of::<Value>())?;
+    list.borrow_mut().items_mut()?.push(args[1].clone());
+
+    Ok(Value::Nil)
+}
+
+pub fn pop(_: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let list = args[0].as_list()?;
+    let item = list.borrow_mut().items_mut()?.pop();
+
+    item.ok_or_else(|| LoxRuntimeException::Error("Can't pop from an empty list".to_owned()))
+}
+
+pub fn insert(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let list = args[0].as_list()?;
+    // Inserting right after the last item is allowed.
+    let index = args[1].as_index(list.borrow().len() + 1)?;
+    environment.allocate(mem::size_of::<Value>())?;
+    list.borrow_mut()
+        .items_mut()?
+        .insert(index, args[2].clone());
+
+    Ok(Value::Nil)
+}
+
+pub fn remove(_: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let list = args[0].as_list()?;
+    let index = args[1].as_index(list.borrow().len())?;
+    let item = list.borrow_mut().items_mut()?.remove(index);
+
+    Ok(item)
+}
+
+pub fn slice(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let items = args[0].as_list()?.borrow().clone();
+    // Both bounds may be equal to the length, giving an empty slice.
+    let start = args[1].as_index(items.len() + 1)?;
+    let end = args[2].as_index(items.len() + 1)?;
+
+    if start > end {
+        return Err(LoxRuntimeException::Error(format!(
+            "Slice start {} is after its end {}",
+            start, end
+        )));
+    }
+
+    environment.new_list(items[start..end].to_vec())
+}
+
+pub fn map(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    // The callback may change the list, so iterate over a copy of it.
+    let items = args[0].as_list()?.borrow().clone();
+
+    let mut mapped = vec![];
+    for item in items {
+        mapped.push(args[1].call(environment, vec![item])?);
+    }
+
+    environment.new_list(mapped)
+}
+
+pub fn filter(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let items = args[0].as_list()?.borrow().clone();
+
+    let mut filtered = vec![];
+    for item in items {
+        if args[1].call(environment, vec![item.clone()])?.is_truthy() {
+            filtered.push(item);
+        }
+    }
+
+    environment.new_list(filtered)
+}
+
+pub fn sort(_: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let list = args[0].as_list()?;
+    let mut items = list.borrow().clone();
+
+    if items
+        .iter()
+        .all(|item| matches!(item, Value::Integer(_) | Value::Number(_)))
+    {
+        items.sort_by(|a, b| match (a, b) {
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Integer(a), Value::Number(b)) => (*a as f64).total_cmp(b),
+            (Value::Number(a), Value::Integer(b)) => a.total_cmp(&(*b as f64)),
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            _ => unreachable!("All items are numbers"),
+        });
+    } else if items.iter().all(|item| matches!(item, Value::String(_))) {
+        items.sort_by(|a, b| match (a, b) {
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => unreachable!("All items are strings"),
+        });
+    } else {
+        return Err(LoxRuntimeException::Error(
+            "Can only sort lists of numbers or lists of strings".to_owned(),
+        ));
+    }
+
+    *list.borrow_mut().items_mut()? = items;
+
+    Ok(Value::Nil)
+}
+
+pub fn keys(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let keys = args[0]
+        .as_map()?
+        .borrow()
+        .keys()
+        .map(Key::to_value)
+        .collect();
+
+    environment.new_list(keys)
+}
+
+pub fn has(_: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let key = Key::new(&args[1])?;
+
+    Ok(Value::Boolean(
+        args[0].as_map()?.borrow().contains_key(&key),
+    ))
+}
+
+pub fn delete(_: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let key = Key::new(&args[1])?;
+    let map = args[0].as_map()?;
+    map.borrow().check_mutable()?;
+    let value = map.borrow_mut().remove(&key);
+
+    Ok(value.unwrap_or(Value::Nil))
+}
+
+/// Makes a list or a map read-only and returns it. The collections inside it
+/// are left as they are.
+pub fn freeze(_: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    match &args[0] {
+        Value::List(list) => list.borrow_mut().freeze(),
+        Value::Map(map) => map.borrow_mut().freeze(),
+        value => {
+            return Err(LoxRuntimeException::Error(format!(
+                "Only lists and maps can be frozen, got {}",
+                value
+            )))
+        }
+    }
+
+    Ok(args[0].clone())
+}
+
+pub fn next(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    match &args[0] {
+        generator @ Value::Generator(_) => generator.call(environment, vec![]),
+        value => Err(LoxRuntimeException::Error(format!(
+            "{} is not a generator",
+            value
+        ))),
+    }
+}
+
+/// Runs a function as a task of the event loop, returning a future of its
+/// return value.
+pub fn spawn(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let Value::Callable(name, closure, parameters, body) = &args[0] else {
+        return Err(LoxRuntimeException::Error(format!(
+            "{} is not a function",
+            args[0]
+        )));
+    };
+
+    let local_environment = closure.new_local();
+    bind_arguments(name, parameters, vec![], vec![], &local_environment)?;
+    let machine = Machine::new_task(vec![(**body).clone()], local_environment);
+
+    let result = environment.new_future()?;
+    environment
+        .runtime()
+        .scheduler
+        .borrow_mut()
+        .spawn(machine, result.clone());
+
+    Ok(Value::Future(result))
+}
+
+/// A future resolved once the event loop's virtual time moved by the given
+/// number of milliseconds.
+pub fn sleep(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let milliseconds = args[0].as_number()?;
+    if milliseconds.is_nan() || milliseconds < 0. {
+        return Err(LoxRuntimeException::Error(format!(
+            "Can't sleep for {} milliseconds",
+            args[0]
+        )));
+    }
+
+    let future = environment.new_future()?;
+    environment
+        .runtime()
+        .scheduler
+        .borrow_mut()
+        .sleep(milliseconds, future.clone());
+
+    Ok(Value::Future(future))
+}
+
+pub fn channel(environment: &Environment, _: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    environment.new_channel()
+}
+
+pub fn send(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let channel = args[0].as_channel()?;
+    environment.allocate(mem::size_of::<Value>())?;
+    channel.borrow_mut().send(args[1].clone());
+
+    Ok(Value::Nil)
+}
+
+/// A future of the next value sent on the channel.
+pub fn receive(environment: &Environment, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    let channel = args[0].as_channel()?;
+    let future = environment.new_future()?;
+    channel.borrow_mut().receive(future.clone());
+
+    Ok(Value::Future(future))
 }
@@ -0,0 +1,28 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where the `clock` native reads the time from, in seconds since the epoch.
+pub trait TimeSource {
+    fn now(&self) -> f64;
+}
+
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> f64 {
+        let since_the_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis();
+
+        since_the_epoch as f64 / 1000.
+    }
+}
+
+/// A clock stuck at the given time, for runs that must be reproducible.
+pub struct FixedTimeSource(pub f64);
+
+impl TimeSource for FixedTimeSource {
+    fn now(&self) -> f64 {
+        self.0
+    }
+}
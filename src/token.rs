@@ -1,4 +1,4 @@
-use std::{fmt, iter::Peekable, vec::IntoIter};
+use std::fmt;
 
 use crate::{error::LoxError, types::Line};
 
@@ -8,40 +8,80 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
+    Ellipsis,
     Minus,
+    MinusEqual,
     Plus,
+    PlusEqual,
+    Question,
     Semicolon,
     Slash,
+    SlashEqual,
     Star,
+    StarEqual,
+    StarStar,
+    Percent,
+    /// `~/`, floor division. `//` already starts a comment.
+    TildeSlash,
+    Tilde,
+    Ampersand,
+    Pipe,
+    Caret,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
     Identifier(String),
     String(String),
+    /// A string with embedded `${...}` expressions.
+    Interpolation(Vec<StringPart>),
+    /// A line of `///` comment, which the scanner attaches to the next token.
+    DocComment(String),
+    Integer(i64),
     Number(f64),
     And,
+    As,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     Fun,
+    From,
     For,
     If,
+    Import,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
+    Yield,
+    Await,
+    Enum,
+    Match,
+    Const,
     Eof,
 }
 
@@ -51,11 +91,20 @@ impl fmt::Display for TokenType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Literal(String),
+    /// The tokens of an embedded expression, ending with EOF.
+    Expression(Vec<Token>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: Line,
+    /// The `///` comments right before the token, one line each.
+    pub doc_comment: Option<String>,
 }
 
 impl fmt::Display for Token {
@@ -66,19 +115,21 @@ impl fmt::Display for Token {
 
 #[derive(Debug)]
 pub struct Tokens {
-    peekable: Peekable<IntoIter<Token>>,
+    tokens: Vec<Token>,
+    position: usize,
 }
 
 impl Tokens {
-    pub fn new<'a>(v: Vec<Token>) -> Tokens {
+    pub fn new(v: Vec<Token>) -> Tokens {
         Tokens {
-            peekable: v.into_iter().peekable(),
+            tokens: v,
+            position: 0,
         }
     }
 
     pub fn peek(&mut self) -> Token {
-        self.peekable
-            .peek()
+        self.tokens
+            .get(self.position)
             .expect("Tokens should not be read after EOF")
             .clone()
     }
@@ -87,10 +138,27 @@ impl Tokens {
         self.peek().token_type
     }
 
+    /// Looks `n` tokens past the next one, stopping at the final EOF.
+    pub fn peek_type_after(&mut self, n: usize) -> TokenType {
+        let position = (self.position + n).min(self.tokens.len() - 1);
+
+        self.tokens[position].token_type.clone()
+    }
+
     pub fn next(&mut self) -> Token {
-        self.peekable
-            .next()
-            .expect("Tokens should not be read after EOF")
+        let token = self.peek();
+        self.position += 1;
+
+        token
+    }
+
+    /// Where the parser currently is, to come back to it with `rewind`.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn rewind(&mut self, position: usize) {
+        self.position = position;
     }
 
     pub fn consume(&mut self, token_type: TokenType) -> Result<(), LoxError> {
@@ -107,10 +175,14 @@ impl Tokens {
     }
 
     pub fn consume_until_semicolon(&mut self) -> Option<()> {
-        self.peekable
-            .by_ref()
-            .skip_while(|token| token.token_type != TokenType::Semicolon)
-            .next()
-            .map(|_| ())
+        while let Some(token) = self.tokens.get(self.position) {
+            self.position += 1;
+
+            if token.token_type == TokenType::Semicolon {
+                return Some(());
+            }
+        }
+
+        None
     }
 }
@@ -1,43 +1,149 @@
-use std::fmt::Display;
+use std::{fmt::Display, rc::Rc};
 
 use crate::{
-    environment::Environment, error::LoxRuntimeException, grammar::Declaration,
-    interpreter::interpret,
+    environment::Environment,
+    error::LoxRuntimeException,
+    event_loop::HostValue,
+    gc::{address, Channel, Future, Generator, List, Map},
+    generator::State,
+    grammar::{Declaration, Enum, Parameter},
+    interpreter::{evaluate, interpret},
+    map::Key,
+    types::Line,
 };
+pub type Native = fn(&Environment, Vec<Value>) -> Result<Value, LoxRuntimeException>;
+
 #[derive(Clone)]
 pub enum Value {
     Nil,
+    Integer(i64),
     Number(f64),
     String(String),
     Boolean(bool),
-    NativeCallable(String, usize, fn(Vec<Value>) -> Value),
-    Callable(String, Environment, Vec<String>, Declaration),
+    NativeCallable(String, usize, Native),
+    Callable(String, Environment, Vec<Parameter>, Rc<Declaration>),
+    List(List),
+    Map(Map),
+    /// A module imported from the given path, with its global environment.
+    Module(String, Environment),
+    /// Returned by functions containing a `yield`. Calling it, or passing it
+    /// to `next`, resumes it up to the next `yield`, or gives nil once done.
+    Generator(Generator),
+    /// The result of an asynchronous operation, which tasks `await`.
+    Future(Future),
+    Channel(Channel),
+    /// A function of the embedding host, whose calls return futures.
+    HostFunction(String, usize),
+    /// An enum, whose variants are read as its properties.
+    Enum(Rc<Enum>),
+    /// The variant at the given index of an enum, with its fields if it
+    /// has some.
+    Variant(Rc<Enum>, usize, Option<List>),
+    /// Called to build the variant at the given index, which has fields.
+    Constructor(Rc<Enum>, usize),
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, &mut vec![])
+    }
+}
+
+impl Value {
+    /// Writes the value, printing lists already being written as `[...]`
+    /// so that a list containing itself does not recurse forever.
+    fn write(&self, f: &mut std::fmt::Formatter<'_>, seen: &mut Vec<usize>) -> std::fmt::Result {
         match self {
             Value::Nil => write!(f, "nil"),
-            Value::Number(n) if n.fract() == 0. => write!(f, "{}", n),
-            Value::Number(n) => write!(f, "{:.2}", n),
+            Value::Integer(n) => write!(f, "{}", n),
+            // The shortest representation reading back as the same float,
+            // with a trailing `.0` telling integral floats from integers.
+            Value::Number(n) => write!(f, "{:?}", n),
             Value::String(s) => write!(f, "\"{}\"", s),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Callable(name, _, _, _) => write!(f, "<fn {}>", name),
-            Value::NativeCallable(name, _, _) => write!(f, "<fn {}>", name),
+            Value::NativeCallable(name, _, _) | Value::HostFunction(name, _) => {
+                write!(f, "<fn {}>", name)
+            }
+            Value::List(list) if seen.contains(&address(list)) => write!(f, "[...]"),
+            Value::List(list) => {
+                seen.push(address(list));
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.write(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "]")
+            }
+            Value::Module(path, _) => write!(f, "<module {}>", path),
+            Value::Generator(_) => write!(f, "<generator>"),
+            Value::Future(_) => write!(f, "<future>"),
+            Value::Channel(_) => write!(f, "<channel>"),
+            Value::Enum(definition) => write!(f, "<enum {}>", definition.name),
+            Value::Constructor(definition, index) => {
+                let variant = &definition.variants[*index].name;
+                write!(f, "<fn {}.{}>", definition.name, variant)
+            }
+            Value::Variant(definition, index, fields) => {
+                write!(
+                    f,
+                    "{}.{}",
+                    definition.name, definition.variants[*index].name
+                )?;
+                match fields {
+                    None => Ok(()),
+                    Some(fields) if seen.contains(&address(fields)) => write!(f, "(...)"),
+                    Some(fields) => {
+                        seen.push(address(fields));
+                        write!(f, "(")?;
+                        for (i, field) in fields.borrow().iter().enumerate() {
+                            if i > 0 {
+                                write!(f, ", ")?;
+                            }
+                            field.write(f, seen)?;
+                        }
+                        seen.pop();
+                        write!(f, ")")
+                    }
+                }
+            }
+            Value::Map(map) if seen.contains(&address(map)) => write!(f, "{{...}}"),
+            Value::Map(map) => {
+                seen.push(address(map));
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.to_value().write(f, seen)?;
+                    write!(f, ": ")?;
+                    value.write(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "}}")
+            }
         }
     }
-}
 
-impl Value {
-    pub fn is_truthy(&self) -> bool {
+    /// The text of the value inside an interpolated string, where strings
+    /// appear without their quotes.
+    pub fn stringify(&self) -> String {
         match self {
-            Value::Nil | Value::Boolean(false) => false,
-            _ => true,
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
         }
     }
 
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
     pub fn as_number(&self) -> Result<f64, LoxRuntimeException> {
         match *self {
+            Value::Integer(n) => Ok(n as f64),
             Value::Number(n) => Ok(n),
             _ => Err(LoxRuntimeException::Error(format!(
                 "{} is not a number",
@@ -46,26 +152,231 @@ impl Value {
         }
     }
 
-    pub fn call(&self, args: Vec<Value>) -> Result<Value, LoxRuntimeException> {
+    pub fn as_list(&self) -> Result<&List, LoxRuntimeException> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(LoxRuntimeException::Error(format!(
+                "{} is not a list",
+                self
+            ))),
+        }
+    }
+
+    pub fn as_channel(&self) -> Result<&Channel, LoxRuntimeException> {
+        match self {
+            Value::Channel(channel) => Ok(channel),
+            _ => Err(LoxRuntimeException::Error(format!(
+                "{} is not a channel",
+                self
+            ))),
+        }
+    }
+
+    pub fn as_map(&self) -> Result<&Map, LoxRuntimeException> {
+        match self {
+            Value::Map(map) => Ok(map),
+            _ => Err(LoxRuntimeException::Error(format!("{} is not a map", self))),
+        }
+    }
+
+    /// Reads a property: a global of a module, a variant of an enum, a field
+    /// of a variant, or a string key of a map, such as the message and line
+    /// of a caught runtime error.
+    pub fn get(&self, name: &str) -> Result<Value, LoxRuntimeException> {
+        match self {
+            Value::Module(_, environment) => environment.get(name),
+            Value::Enum(definition) => {
+                let index = definition
+                    .variants
+                    .iter()
+                    .position(|variant| variant.name == name)
+                    .ok_or_else(|| {
+                        LoxRuntimeException::Error(format!(
+                            "Enum {} has no variant {}",
+                            definition.name, name
+                        ))
+                    })?;
+
+                match definition.variants[index].fields {
+                    Some(_) => Ok(Value::Constructor(definition.clone(), index)),
+                    None => Ok(Value::Variant(definition.clone(), index, None)),
+                }
+            }
+            Value::Variant(definition, index, fields) => {
+                let position = definition.variants[*index]
+                    .fields
+                    .iter()
+                    .flatten()
+                    .position(|field| field == name);
+
+                match (position, fields) {
+                    (Some(position), Some(fields)) => Ok(fields.borrow()[position].clone()),
+                    _ => Err(LoxRuntimeException::Error(format!(
+                        "{} has no field {}",
+                        self, name
+                    ))),
+                }
+            }
+            Value::Map(map) => map
+                .borrow()
+                .get(&Key::String(name.to_owned()))
+                .cloned()
+                .ok_or_else(|| LoxRuntimeException::Error(format!("{} has no key {}", self, name))),
+            _ => Err(LoxRuntimeException::Error(format!(
+                "Only modules, enums, variants and maps have properties, {} doesn't",
+                self
+            ))),
+        }
+    }
+
+    /// Checks that the value is a valid index in a collection of `len` items.
+    pub fn as_index(&self, len: usize) -> Result<usize, LoxRuntimeException> {
+        let index = match *self {
+            Value::Integer(n) => n,
+            Value::Number(n) if n.fract() == 0. => n as i64,
+            _ => {
+                return Err(LoxRuntimeException::Error(format!(
+                    "Index {} is not an integer",
+                    self
+                )))
+            }
+        };
+
+        if index < 0 {
+            Err(LoxRuntimeException::Error(format!(
+                "Index {} is negative",
+                index
+            )))
+        } else if index as u64 >= len as u64 {
+            Err(LoxRuntimeException::Error(format!(
+                "Index {} is out of bounds for length {}",
+                index, len
+            )))
+        } else {
+            Ok(index as usize)
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, LoxRuntimeException> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(LoxRuntimeException::Error(format!(
+                "{} is not a string",
+                self
+            ))),
+        }
+    }
+
+    pub fn call(
+        &self,
+        environment: &Environment,
+        args: Vec<Value>,
+    ) -> Result<Value, LoxRuntimeException> {
+        self.call_with_named(environment, args, vec![])
+    }
+
+    /// Calls the value with positional arguments and `name: value` ones.
+    pub fn call_with_named(
+        &self,
+        environment: &Environment,
+        args: Vec<Value>,
+        named: Vec<(String, Value)>,
+    ) -> Result<Value, LoxRuntimeException> {
         match self {
             Value::NativeCallable(name, arity, f) => {
+                if !named.is_empty() {
+                    return Err(LoxRuntimeException::Error(format!(
+                        "Function {} doesn't take named arguments.",
+                        name
+                    )));
+                }
                 check_callable_arity(&args, *arity, name)?;
 
-                Ok(f(args))
+                f(environment, args)
+            }
+            Value::Callable(_, _, _, _) => {
+                let (mut callee, mut args, mut named) = (self.clone(), args, named);
+                let mut line = None;
+
+                // Tail calls come back here instead of nesting on the stack.
+                loop {
+                    let Value::Callable(name, closure, parameters, body) = &callee else {
+                        return callee
+                            .call_with_named(environment, args, named)
+                            .map_err(|exception| locate(exception, line));
+                    };
+
+                    let local_environment = closure.new_local();
+                    bind_arguments(name, parameters, args, named, &local_environment)
+                        .map_err(|exception| locate(exception, line))?;
+
+                    match interpret((**body).clone(), &local_environment) {
+                        Ok(_) => return Ok(Value::Nil),
+                        Err(LoxRuntimeException::Return(value)) => return Ok(value),
+                        Err(LoxRuntimeException::TailCall(call)) => {
+                            let call = *call;
+                            (callee, args, named) = (call.callee, call.args, call.named);
+                            line = Some(call.line);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
             }
-            Value::Callable(name, closure, parameters, body) => {
-                check_callable_arity(&args, parameters.len(), name)?;
+            Value::Generator(generator) => {
+                if !named.is_empty() {
+                    return Err(LoxRuntimeException::Error(
+                        "Generators don't take named arguments.".to_owned(),
+                    ));
+                }
+                check_callable_arity(&args, 0, &"generator".to_owned())?;
 
-                let local_environment = closure.new_local();
-                for (name, value) in parameters.iter().zip(args.iter()) {
-                    local_environment.define(name.clone(), Some(value.clone()));
+                let mut generator = generator.try_borrow_mut().map_err(|_| {
+                    LoxRuntimeException::Error("Generator is already running".to_owned())
+                })?;
+                match generator.resume()? {
+                    State::Yielded(value) => Ok(value),
+                    State::Returned(_) => Ok(Value::Nil),
+                    State::Awaiting => unreachable!("The resolver rejects awaits in generators"),
+                }
+            }
+            Value::Constructor(definition, index) => {
+                let variant = &definition.variants[*index];
+                let name = format!("{}.{}", definition.name, variant.name);
+                if !named.is_empty() {
+                    return Err(LoxRuntimeException::Error(format!(
+                        "Function {} doesn't take named arguments.",
+                        name
+                    )));
                 }
+                let arity = variant.fields.as_ref().map_or(0, Vec::len);
+                check_callable_arity(&args, arity, &name)?;
 
-                match interpret(body.clone(), &local_environment) {
-                    Ok(_) => Ok(Value::Nil),
-                    Err(LoxRuntimeException::Return(value)) => Ok(value),
-                    Err(e) => Err(e),
+                let Value::List(fields) = environment.new_list(args)? else {
+                    unreachable!("new_list makes lists");
+                };
+                Ok(Value::Variant(definition.clone(), *index, Some(fields)))
+            }
+            Value::HostFunction(name, arity) => {
+                if !named.is_empty() {
+                    return Err(LoxRuntimeException::Error(format!(
+                        "Function {} doesn't take named arguments.",
+                        name
+                    )));
                 }
+                check_callable_arity(&args, *arity, name)?;
+
+                let args = args
+                    .iter()
+                    .map(HostValue::from_value)
+                    .collect::<Result<_, _>>()?;
+                let future = environment.new_future()?;
+                environment.runtime().scheduler.borrow_mut().call_host(
+                    name.clone(),
+                    args,
+                    future.clone(),
+                );
+
+                Ok(Value::Future(future))
             }
             _ => Err(LoxRuntimeException::Error(
                 "Can only call functions and classes".to_owned(),
@@ -73,19 +384,140 @@ impl Value {
         }
     }
 
+    /// Visits the environments this value keeps alive.
+    pub fn trace(&self, visit: &mut impl FnMut(usize)) {
+        match self {
+            Value::Callable(_, closure, _, _) => closure.trace(visit),
+            Value::List(list) => visit(address(list)),
+            Value::Map(map) => visit(address(map)),
+            Value::Generator(generator) => visit(address(generator)),
+            Value::Future(future) => visit(address(future)),
+            Value::Channel(channel) => visit(address(channel)),
+            Value::Variant(_, _, Some(fields)) => visit(address(fields)),
+            Value::Module(_, environment) => environment.trace(visit),
+            _ => {}
+        }
+    }
+
     pub fn is_equal(&self, other: &Value) -> bool {
         match (self, other) {
             (Value::Nil, Value::Nil) => true,
+            (Value::Integer(n1), Value::Integer(n2)) => n1 == n2,
             (Value::Number(n1), Value::Number(n2)) => n1 == n2,
+            (Value::Integer(n1), Value::Number(n2)) | (Value::Number(n2), Value::Integer(n1)) => {
+                *n1 as f64 == *n2
+            }
             (Value::String(s1), Value::String(s2)) => s1 == s2,
             (Value::Boolean(b1), Value::Boolean(b2)) => b1 == b2,
+            (Value::List(l1), Value::List(l2)) => Rc::ptr_eq(l1, l2),
+            (Value::Map(m1), Value::Map(m2)) => Rc::ptr_eq(m1, m2),
+            (Value::Generator(g1), Value::Generator(g2)) => Rc::ptr_eq(g1, g2),
+            (Value::Future(f1), Value::Future(f2)) => Rc::ptr_eq(f1, f2),
+            (Value::Channel(c1), Value::Channel(c2)) => Rc::ptr_eq(c1, c2),
+            (Value::Enum(e1), Value::Enum(e2)) => Rc::ptr_eq(e1, e2),
+            (Value::Constructor(e1, i1), Value::Constructor(e2, i2)) => {
+                Rc::ptr_eq(e1, e2) && i1 == i2
+            }
+            // Variants are values: the same variant with equal fields is equal.
+            (Value::Variant(e1, i1, f1), Value::Variant(e2, i2, f2)) => {
+                Rc::ptr_eq(e1, e2)
+                    && i1 == i2
+                    && match (f1, f2) {
+                        (Some(f1), Some(f2)) => {
+                            let (f1, f2) = (f1.borrow(), f2.borrow());
+                            f1.iter().zip(f2.iter()).all(|(v1, v2)| v1.is_equal(v2))
+                        }
+                        _ => true,
+                    }
+            }
             _ => false,
         }
     }
 }
 
+/// Defines the parameters in the function's scope. Positional arguments come
+/// first, then named ones, then defaults, which can use earlier parameters.
+pub fn bind_arguments(
+    name: &str,
+    parameters: &[Parameter],
+    args: Vec<Value>,
+    named: Vec<(String, Value)>,
+    local_environment: &Environment,
+) -> Result<(), LoxRuntimeException> {
+    let (fixed, rest) = match parameters.split_last() {
+        Some((last, fixed)) if last.rest => (fixed, Some(last)),
+        _ => (parameters, None),
+    };
+
+    let arity_error = |count: usize| {
+        let required = fixed.iter().filter(|p| p.default.is_none()).count();
+        let expected = match (required, fixed.len(), rest) {
+            (required, _, Some(_)) => format!("at least {}", required),
+            (required, total, None) if required == total => required.to_string(),
+            (required, total, None) => format!("{} to {}", required, total),
+        };
+
+        LoxRuntimeException::Error(format!(
+            "Function {} expected {} arguments but got {}.",
+            name, expected, count
+        ))
+    };
+
+    let count = args.len() + named.len();
+    if args.len() > fixed.len() && rest.is_none() {
+        return Err(arity_error(count));
+    }
+
+    let mut args = args.into_iter();
+    let mut values: Vec<Option<Value>> = args.by_ref().take(fixed.len()).map(Some).collect();
+    values.resize(fixed.len(), None);
+
+    for (argument, value) in named {
+        let position = fixed
+            .iter()
+            .position(|parameter| parameter.name == argument)
+            .ok_or_else(|| {
+                LoxRuntimeException::Error(format!(
+                    "Function {} has no parameter named {}.",
+                    name, argument
+                ))
+            })?;
+
+        if values[position].replace(value).is_some() {
+            return Err(LoxRuntimeException::Error(format!(
+                "Function {} got argument {} twice.",
+                name, argument
+            )));
+        }
+    }
+
+    for (parameter, value) in fixed.iter().zip(values) {
+        let value = match (value, &parameter.default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => evaluate(default.clone(), local_environment)?,
+            (None, None) => return Err(arity_error(count)),
+        };
+        local_environment.define(parameter.name.clone(), Some(value));
+    }
+
+    if let Some(rest) = rest {
+        let list = local_environment.new_list(args.collect())?;
+        local_environment.define(rest.name.clone(), Some(list));
+    }
+
+    Ok(())
+}
+
+/// Errors of tail calls are located at the `return` making them.
+fn locate(exception: LoxRuntimeException, line: Option<Line>) -> LoxRuntimeException {
+    match line {
+        Some(line) => exception.at(line),
+        None => exception,
+    }
+}
+
 fn check_callable_arity(
-    args: &Vec<Value>,
+    args: &[Value],
     arity: usize,
     name: &String,
 ) -> Result<(), LoxRuntimeException> {
@@ -0,0 +1,78 @@
+mod common;
+
+use common::Scripts;
+use rlox::{Capability, Config, Lox};
+
+fn without_filesystem() -> Config {
+    Config {
+        capabilities: Capability::ALL
+            .into_iter()
+            .filter(|capability| *capability != Capability::Filesystem)
+            .collect(),
+        ..Config::default()
+    }
+}
+
+fn scripts() -> Scripts {
+    let scripts = Scripts::new();
+    scripts
+        .write("data.txt", "data")
+        .write("module.lox", "var answer = 42;");
+
+    scripts
+}
+
+#[test]
+fn reading_files_needs_the_filesystem_capability() {
+    let scripts = scripts();
+    let lox = Lox::new(without_filesystem());
+
+    let result = lox.run(&format!(
+        "try {{
+            readFile(\"{}\");
+            throw \"the file was read\";
+        }} catch (error) {{
+            if (error.message != \"readFile needs the filesystem capability, which is not allowed\")
+                throw error;
+        }}",
+        scripts.path("data.txt")
+    ));
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn importing_needs_the_filesystem_capability() {
+    let scripts = scripts();
+    scripts.write(
+        "main.lox",
+        "import \"module.lox\" as module;
+        if (module.answer != 42) throw \"wrong module\";",
+    );
+
+    // Imports are only allowed at the top level, out of reach of `catch`,
+    // so the error is told apart by running the same file with the
+    // capability.
+    let lox = Lox::new(without_filesystem());
+    assert!(lox.run_file(&scripts.path("main.lox")).is_err());
+
+    let lox = Lox::new(Config::default());
+    assert!(lox.run_file(&scripts.path("main.lox")).is_ok());
+}
+
+#[test]
+fn the_default_config_allows_the_filesystem() {
+    let scripts = scripts();
+    scripts.write(
+        "main.lox",
+        &format!(
+            "if (readFile(\"{}\") != \"data\") throw \"wrong content\";
+            import \"module.lox\" as module;
+            if (module.answer != 42) throw \"wrong module\";",
+            scripts.path("data.txt")
+        ),
+    );
+    let lox = Lox::new(Config::default());
+
+    assert!(lox.run_file(&scripts.path("main.lox")).is_ok());
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use rlox::{Config, Lox};
+
+#[test]
+fn block_comments_nest() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var x = 1;
+        /* x = 2;
+           /* nested */
+           x = 3; */
+        /// Documented.
+        fun get() { return x; }
+        if (get() != 1) throw \"commented code ran\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn unterminated_block_comments_report_their_opening_line() {
+    let output = common::run(
+        "print 1;
+        /* outer
+        /* inner */
+        print 2;",
+    );
+
+    assert_eq!(output, "[Line 2] Error: Unterminated block comment\n");
+}
@@ -0,0 +1,63 @@
+// Each test file uses its own part of these helpers.
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A temporary directory of scripts, run with the `rlox` binary to check
+/// what they print, errors included.
+pub struct Scripts {
+    directory: PathBuf,
+}
+
+impl Scripts {
+    pub fn new() -> Scripts {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let directory = env::temp_dir().join(format!(
+            "rlox-test-{}-{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&directory).unwrap();
+
+        Scripts { directory }
+    }
+
+    pub fn write(&self, name: &str, source: &str) -> &Scripts {
+        let path = self.directory.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+
+        self
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        self.directory.join(name).display().to_string()
+    }
+
+    /// Runs a script written before, returning its output.
+    pub fn run(&self, name: &str) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+            .arg(self.path(name))
+            .output()
+            .unwrap();
+
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+impl Drop for Scripts {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+/// Runs the source as a script file, returning its output.
+pub fn run(source: &str) -> String {
+    Scripts::new().write("main.lox", source).run("main.lox")
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use rlox::{Config, Lox};
+
+#[test]
+fn assigning_to_constants_is_a_compile_error() {
+    let output = common::run(
+        "const a = 1;
+        print \"ran\";
+        a = 2;
+        fun f() {
+            const b = [];
+            b += 1;
+        }
+        fun g() { a = 3; }",
+    );
+
+    assert_eq!(
+        output,
+        "[Line 3] Error: Can't assign to constant a.\n\
+        [Line 6] Error: Can't assign to constant b.\n\
+        [Line 8] Error: Can't assign to constant a.\n"
+    );
+}
+
+#[test]
+fn redeclared_constants_are_reported_at_the_redeclaration() {
+    let output = common::run(
+        "const a = 1;
+
+        fun a() {}
+        enum a { X }",
+    );
+
+    assert_eq!(
+        output,
+        "[Line 3] Error: Can't redeclare constant a.\n\
+        [Line 4] Error: Can't redeclare constant a.\n"
+    );
+}
+
+#[test]
+fn constants_can_hold_mutable_values() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "const list = [1];
+        push(list, 2);
+        if (len(list) != 2) throw \"the list was frozen\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn frozen_lists_and_maps_refuse_changes() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var list = freeze([1]);
+        var map = freeze({\"a\": 1});
+        var errors = [];
+        try { push(list, 2); } catch (error) { push(errors, error.message); }
+        try { list[0] = 2; } catch (error) { push(errors, error.message); }
+        try { map[\"a\"] = 2; } catch (error) { push(errors, error.message); }
+        try { delete(map, \"a\"); } catch (error) { push(errors, error.message); }
+
+        if (len(errors) != 4) throw \"a frozen value changed\";
+        if (errors[0] != \"Can't modify a frozen list\") throw errors[0];
+        if (errors[3] != \"Can't modify a frozen map\") throw errors[3];
+        if (list[0] != 1 or map[\"a\"] != 1) throw \"a frozen value changed\";",
+    );
+
+    assert!(result.is_ok());
+}
@@ -0,0 +1,277 @@
+use rlox::{Config, EventLoop, HostCall, HostValue};
+
+/// Stands in for the I/O of an embedder: `log` calls are recorded and
+/// answered at once, the other calls are kept for the test to settle.
+struct MockHost {
+    event_loop: EventLoop,
+    logs: Vec<HostValue>,
+    calls: Vec<HostCall>,
+}
+
+impl MockHost {
+    fn new(source: &str) -> MockHost {
+        let event_loop = EventLoop::new(Config::default().deterministic());
+        event_loop.define_host_function("log", 1);
+        event_loop.define_host_function("fetch", 1);
+        event_loop.spawn(source).unwrap();
+
+        MockHost {
+            event_loop,
+            logs: vec![],
+            calls: vec![],
+        }
+    }
+
+    /// Runs the tasks until they only wait for the test.
+    fn run(&mut self) {
+        loop {
+            self.event_loop.run_until_idle().unwrap();
+
+            let calls = self.event_loop.take_host_calls();
+            if calls.is_empty() {
+                return;
+            }
+
+            for call in calls {
+                if call.name == "log" {
+                    self.logs.push(call.args[0].clone());
+                    self.event_loop.resolve(call.id, HostValue::Nil).unwrap();
+                } else {
+                    self.calls.push(call);
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self, milliseconds: f64) {
+        self.event_loop.advance(milliseconds).unwrap();
+        self.run();
+    }
+
+    fn take_logs(&mut self) -> Vec<HostValue> {
+        std::mem::take(&mut self.logs)
+    }
+}
+
+fn string(s: &str) -> HostValue {
+    HostValue::String(s.to_owned())
+}
+
+#[test]
+fn tasks_await_host_calls() {
+    let mut host = MockHost::new(
+        "var page = await fetch(\"a\");
+        await log(\"got \" + page);
+        try {
+            await fetch(\"b\");
+        } catch (error) {
+            await log(\"failed with \" + error);
+        }",
+    );
+
+    host.run();
+    assert!(host.take_logs().is_empty());
+    let call = host.calls.remove(0);
+    assert_eq!(call.name, "fetch");
+    assert_eq!(call.args, vec![string("a")]);
+
+    host.event_loop.resolve(call.id, string("page a")).unwrap();
+    host.run();
+    assert_eq!(host.take_logs(), vec![string("got page a")]);
+
+    let call = host.calls.remove(0);
+    host.event_loop.reject(call.id, "timeout").unwrap();
+    host.run();
+    assert_eq!(host.take_logs(), vec![string("failed with timeout")]);
+    assert!(host.event_loop.is_finished());
+}
+
+#[test]
+fn sleeps_follow_virtual_time() {
+    let mut host = MockHost::new(
+        "fun after(delay, name) {
+            return fun() {
+                await sleep(delay);
+                await log(name);
+                return delay;
+            };
+        }
+
+        var slow = spawn(after(30, \"slow\"));
+        spawn(after(10, \"fast\"));
+        var total = await slow;
+        await log(total);",
+    );
+
+    host.run();
+    host.advance(5.);
+    assert!(host.take_logs().is_empty());
+
+    host.advance(20.);
+    assert_eq!(host.take_logs(), vec![string("fast")]);
+    assert_eq!(host.event_loop.now(), 25.);
+
+    host.advance(5.);
+    assert_eq!(
+        host.take_logs(),
+        vec![string("slow"), HostValue::Integer(30)]
+    );
+    assert!(host.event_loop.is_finished());
+}
+
+#[test]
+fn channels_fan_out_work() {
+    let mut host = MockHost::new(
+        "var jobs = channel();
+        var results = channel();
+
+        fun worker(name) {
+            return fun() {
+                while (true) {
+                    var job = await receive(jobs);
+                    if (job == nil) return;
+                    var page = await fetch(job);
+                    send(results, name + \": \" + page);
+                }
+            };
+        }
+
+        spawn(worker(\"first\"));
+        spawn(worker(\"second\"));
+        for (job in [\"a\", \"b\", \"c\"]) send(jobs, job);
+
+        for (var i = 0; i < 3; i++) {
+            var result = await receive(results);
+            await log(result);
+        }
+        send(jobs, nil);
+        send(jobs, nil);",
+    );
+
+    host.run();
+    let mut fetched: Vec<_> = host.calls.drain(..).collect();
+    assert_eq!(fetched.len(), 2);
+
+    // The second job comes back first, freeing its worker for the third.
+    for call in [fetched.remove(1), fetched.remove(0)] {
+        let HostValue::String(job) = &call.args[0] else {
+            panic!("fetch takes a string");
+        };
+        let page = HostValue::String(format!("page {}", job));
+        host.event_loop.resolve(call.id, page).unwrap();
+        host.run();
+    }
+
+    let call = host.calls.remove(0);
+    assert_eq!(call.args, vec![string("c")]);
+    host.event_loop.resolve(call.id, string("page c")).unwrap();
+    host.run();
+
+    assert_eq!(
+        host.take_logs(),
+        vec![
+            string("second: page b"),
+            string("first: page a"),
+            string("second: page c"),
+        ]
+    );
+    assert!(host.event_loop.is_finished());
+}
+
+#[test]
+fn failing_tasks_reject_their_future() {
+    let mut host = MockHost::new(
+        "var failing = spawn(fun() { throw \"broken\"; });
+        spawn(fun() { await log(\"still running\"); });
+        try {
+            await failing;
+        } catch (error) {
+            await log(error);
+        }",
+    );
+
+    // The failure is handled by the script, so the loop carries on.
+    host.run();
+    assert_eq!(
+        host.take_logs(),
+        vec![string("still running"), string("broken")]
+    );
+    assert!(host.event_loop.is_finished());
+}
+
+#[test]
+fn failing_scripts_stop_the_loop() {
+    let host = MockHost::new("throw \"broken\";");
+
+    assert!(host.event_loop.run_until_idle().is_err());
+}
+
+#[test]
+fn functions_called_by_tasks_can_await() {
+    let mut host = MockHost::new(
+        "fun load(name) {
+            var page = await fetch(name);
+            if (page == \"\") throw \"empty \" + name;
+            return name + \": \" + page;
+        }
+
+        fun loadBoth() {
+            var first = load(\"a\");
+            return loadSecond(first);
+        }
+
+        fun loadSecond(first) {
+            var second = load(\"b\");
+            return first + \", \" + second;
+        }
+
+        fun report(text) {
+            await log(text);
+        }
+
+        var both;
+        both = loadBoth();
+        report(both);
+        try {
+            load(\"c\");
+        } catch (error) {
+            report(error);
+        }",
+    );
+
+    host.run();
+    for page in ["page a", "page b", ""] {
+        let call = host.calls.remove(0);
+        host.event_loop.resolve(call.id, string(page)).unwrap();
+        host.run();
+    }
+
+    assert_eq!(
+        host.take_logs(),
+        vec![string("a: page a, b: page b"), string("empty c")]
+    );
+    assert!(host.event_loop.is_finished());
+}
+
+#[test]
+fn lists_containing_themselves_are_not_passed_to_the_host() {
+    let mut host = MockHost::new(
+        "var list = [1];
+        push(list, list);
+        try {
+            await log(list);
+        } catch (error) {
+            await log(error.message);
+        }",
+    );
+
+    host.run();
+
+    assert_eq!(
+        host.take_logs(),
+        vec![string(
+            "[1, [...]] contains itself and can't be passed to the host"
+        )]
+    );
+    assert!(host.event_loop.is_finished());
+}
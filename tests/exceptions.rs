@@ -0,0 +1,100 @@
+use rlox::{Config, Lox};
+
+#[test]
+fn runtime_errors_are_caught_with_their_message_and_line() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "try {
+            print 1 - \"a\";
+        } catch (error) {
+            if (error.message != \"\\\"a\\\" is not a number\") throw \"wrong message\";
+            if (error.line != 2) throw \"wrong line\";
+        }
+
+        fun one(a) {}
+        try {
+            one(1, 2);
+        } catch (error) {
+            if (error.message != \"Function one expected 1 arguments but got 2.\")
+                throw \"wrong message\";
+            if (error.line != 10) throw \"wrong line\";
+        }",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn thrown_values_are_caught_as_they_are() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "try { throw [1, 2]; } catch (error) {
+            if (error[1] != 2) throw \"wrong list\";
+        }
+        try { throw {\"code\": 404}; } catch (error) {
+            if (error.code != 404) throw \"wrong map\";
+        }
+        try { throw 42; } catch (error) {
+            if (error != 42) throw \"wrong number\";
+        }",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn finally_runs_on_every_exit() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var exits = [];
+
+        try { push(exits, \"body\"); } finally { push(exits, \"normal\"); }
+
+        fun returning() {
+            try { return 1; } finally { push(exits, \"return\"); }
+        }
+        if (returning() != 1) throw \"wrong return value\";
+
+        try {
+            try { throw \"error\"; } catch (error) { throw error; } finally { push(exits, \"rethrow\"); }
+        } catch (error) {
+            if (error != \"error\") throw \"wrong rethrown error\";
+        }
+
+        var expected = [\"body\", \"normal\", \"return\", \"rethrow\"];
+        if (len(exits) != len(expected)) throw \"wrong number of exits\";
+        for (var i = 0; i < len(exits); i += 1) {
+            if (exits[i] != expected[i]) throw \"wrong exit\";
+        }",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn finally_runs_before_an_uncaught_error() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run("var ran = false; try { throw \"error\"; } finally { ran = true; }");
+    assert!(result.is_err());
+
+    assert!(lox.run("if (!ran) throw \"finally skipped\";").is_ok());
+}
+
+#[test]
+fn throwing_from_finally_replaces_the_error() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "try {
+            try { throw \"first\"; } finally { throw \"second\"; }
+        } catch (error) {
+            if (error != \"second\") throw \"the first error was kept\";
+        }",
+    );
+
+    assert!(result.is_ok());
+}
@@ -0,0 +1,87 @@
+use rlox::{Config, Lox};
+
+#[test]
+fn arrows_are_told_apart_from_parenthesized_expressions() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var x = 5;
+        if ((x) != 5) throw \"(x) is not x\";
+        if ((1 + 2) * 3 != 9) throw \"wrong grouping\";
+
+        var add = (a, b) => a + b;
+        var seven = () => 7;
+        var double = (x) => x * 2;
+        if (add(1, 2) != 3 or seven() != 7 or double(4) != 8) throw \"wrong arrows\";
+
+        fun increment(n) { return n + 1; }
+        var compose = (f, g) => (x) => f(g(x));
+        if (compose(double, increment)(3) != 8) throw \"wrong composition\";
+
+        var squares = map([1, 2], (n) => n * n);
+        if (squares[1] != 4) throw \"wrong squares\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn anonymous_functions_close_over_their_environment() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun counter() {
+            var count = 0;
+            return fun () { count = count + 1; return count; };
+        }
+        var next = counter();
+        next();
+        if (next() != 2) throw \"wrong count\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn arguments_fill_defaults_rest_and_named_parameters() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun add(a, b = 2) { return a + b; }
+        fun count(first, ...rest) { return len(rest); }
+        if (add(1) != 3 or add(1, 5) != 6) throw \"wrong defaults\";
+        if (add(1, b: 5) != 6 or add(a: 1) != 3) throw \"wrong named arguments\";
+        if (count(1, 2, 3) != 2 or count(1) != 0) throw \"wrong rest\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn wrong_arguments_are_errors() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun add(a, b = 2) { return a + b; }
+        fun count(first, ...rest) { return len(rest); }
+        var errors = [];
+        try { add(); } catch (error) { push(errors, error.message); }
+        try { add(1, 2, 3); } catch (error) { push(errors, error.message); }
+        try { count(); } catch (error) { push(errors, error.message); }
+        try { add(1, a: 5); } catch (error) { push(errors, error.message); }
+        try { add(1, c: 5); } catch (error) { push(errors, error.message); }
+
+        var expected = [
+            \"Function add expected 1 to 2 arguments but got 0.\",
+            \"Function add expected 1 to 2 arguments but got 3.\",
+            \"Function count expected at least 1 arguments but got 0.\",
+            \"Function add got argument a twice.\",
+            \"Function add has no parameter named c.\"
+        ];
+        for (var i = 0; i < len(expected); i += 1) {
+            if (errors[i] != expected[i]) throw errors[i];
+        }",
+    );
+
+    assert!(result.is_ok());
+}
@@ -0,0 +1,83 @@
+use rlox::{Config, Lox};
+
+#[test]
+fn unreachable_cycles_are_collected() {
+    let lox = Lox::new(Config::default());
+    lox.collect_garbage();
+    let baseline = lox.heap_stats();
+
+    // The closure holds the block's environment, which holds the list,
+    // which holds the closure.
+    let result = lox.run(
+        "{
+            var list = [\"some text\"];
+            fun get() { return list; }
+            push(list, get);
+        }",
+    );
+    assert!(result.is_ok());
+    assert!(lox.heap_stats().objects > baseline.objects);
+
+    lox.collect_garbage();
+    let stats = lox.heap_stats();
+
+    assert_eq!(stats.objects, baseline.objects);
+    assert_eq!(stats.allocated, baseline.allocated);
+    assert!(stats.freed > baseline.freed);
+}
+
+#[test]
+fn values_reachable_from_globals_survive() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var kept = [\"some text\"];
+        fun get() { return kept; }
+        push(kept, get);",
+    );
+    assert!(result.is_ok());
+
+    lox.collect_garbage();
+    let stats = lox.heap_stats();
+    lox.collect_garbage();
+    assert_eq!(lox.heap_stats().objects, stats.objects);
+    assert_eq!(lox.heap_stats().allocated, stats.allocated);
+
+    let result = lox.run(
+        "if (kept[0] != \"some text\") throw \"the list was emptied\";
+        if (kept[1]() != kept) throw \"the closure was emptied\";",
+    );
+    assert!(result.is_ok());
+}
+
+#[test]
+fn generators_and_closures_run_under_stress() {
+    let lox = Lox::new(Config {
+        gc_stress: true,
+        ..Config::default()
+    });
+
+    let result = lox.run(
+        "fun counter() {
+            var count = 0;
+            fun increment() { count += 1; return count; }
+            return increment;
+        }
+
+        fun numbers(limit) {
+            var increment = counter();
+            for (var i = 0; i < limit; i += 1) yield [increment(), {\"i\": i}];
+        }
+
+        var total = 0;
+        for (var pair in numbers(20)) {
+            var cycle = [pair];
+            push(cycle, cycle);
+            total += pair[0] + pair[1][\"i\"];
+        }
+        if (total != 400) throw \"wrong total\";",
+    );
+
+    assert!(result.is_ok());
+    assert!(lox.heap_stats().collections > 20);
+}
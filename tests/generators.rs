@@ -0,0 +1,52 @@
+use rlox::{Config, Lox};
+
+#[test]
+fn finished_generators_keep_giving_nil() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun two() { yield 1; yield 2; }
+        var numbers = two();
+        if (numbers() != 1 or next(numbers) != 2) throw \"wrong values\";
+        if (numbers() != nil) throw \"no nil at the end\";
+        if (numbers() != nil or next(numbers) != nil) throw \"resumed after the end\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn generators_that_failed_are_finished() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun failing() { yield 1; throw \"failed\"; yield 2; }
+        var values = failing();
+        values();
+        try {
+            values();
+            throw \"no error\";
+        } catch (error) {
+            if (error != \"failed\") throw error;
+        }
+        if (values() != nil) throw \"resumed after failing\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn generators_keep_their_state_apart() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun naturals() { var n = 0; while (true) { yield n; n = n + 1; } }
+        var first = naturals();
+        var second = naturals();
+        first();
+        first();
+        if (first() != 2 or second() != 0) throw \"shared state\";",
+    );
+
+    assert!(result.is_ok());
+}
@@ -0,0 +1,40 @@
+use rlox::{Config, Lox};
+
+#[test]
+fn indexes_out_of_the_list_are_errors() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var list = [1, 2];
+        var errors = [];
+        try { list[-1]; } catch (error) { push(errors, error.message); }
+        try { list[2]; } catch (error) { push(errors, error.message); }
+        try { list[5] = 3; } catch (error) { push(errors, error.message); }
+        try { list[0.5]; } catch (error) { push(errors, error.message); }
+
+        if (errors[0] != \"Index -1 is negative\") throw errors[0];
+        if (errors[1] != \"Index 2 is out of bounds for length 2\") throw errors[1];
+        if (errors[2] != \"Index 5 is out of bounds for length 2\") throw errors[2];
+        if (errors[3] != \"Index 0.5 is not an integer\") throw errors[3];
+        if (len(list) != 2) throw \"the list changed\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn lists_are_indexed_and_assigned() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var list = [1, [2, 3]];
+        list[1][0] = 4;
+        list[0] = list[0] + 1;
+        push(list, 5);
+        if (list[0] != 2 or list[1][0] != 4 or list[2] != 5) throw \"wrong items\";
+        if (list[1.0][1] != 3) throw \"integral floats are not indexes\";
+        if (pop(list) != 5 or len(list) != 2) throw \"wrong pop\";",
+    );
+
+    assert!(result.is_ok());
+}
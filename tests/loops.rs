@@ -0,0 +1,92 @@
+mod common;
+
+use rlox::{Config, Lox};
+
+#[test]
+fn continue_in_a_for_loop_runs_the_increment() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var odds = 0;
+        for (var i = 0; i < 10; i = i + 1) {
+            if (i % 2 == 0) continue;
+            odds = odds + 1;
+        }
+        if (odds != 5) throw \"wrong count of odd numbers\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn break_leaves_the_innermost_loop() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var rounds = 0;
+        while (true) {
+            for (var i = 0; true; i = i + 1) {
+                if (i == 3) break;
+            }
+            rounds = rounds + 1;
+            if (rounds == 2) break;
+        }
+        if (rounds != 2) throw \"wrong number of rounds\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn break_and_continue_outside_of_loops_are_rejected() {
+    let output = common::run(
+        "break;
+        while (true) {
+            fun f() { continue; }
+            break;
+        }",
+    );
+
+    assert_eq!(
+        output,
+        "[Line 1] Error: Can't use 'break' outside of a loop.\n\
+        [Line 3] Error: Can't use 'continue' outside of a loop.\n"
+    );
+}
+
+#[test]
+fn for_in_goes_over_strings_maps_and_generators() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var characters = [];
+        for (var c in \"héllo\") push(characters, c);
+        if (len(characters) != 5 or characters[1] != \"é\") throw \"wrong characters\";
+
+        var keys = \"\";
+        for (var key in {\"a\": 1, \"b\": 2}) keys = keys + key;
+        if (keys != \"ab\") throw \"wrong keys\";
+
+        fun countdown(n) {
+            while (n > 0) {
+                yield n;
+                n = n - 1;
+            }
+        }
+        var total = 0;
+        for (var n in countdown(3)) total = total + n;
+        if (total != 6) throw \"wrong total\";
+
+        var calls = 0;
+        fun twice() {
+            calls = calls + 1;
+            if (calls > 2) return nil;
+            return calls;
+        }
+        var called = [];
+        for (var n in twice) push(called, n);
+        if (len(called) != 2) throw \"wrong number of calls\";",
+    );
+
+    assert!(result.is_ok());
+}
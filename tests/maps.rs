@@ -0,0 +1,48 @@
+use rlox::{Config, Lox};
+
+#[test]
+fn equal_numbers_are_the_same_key() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var map = {1: \"integer\"};
+        map[1.0] = \"float\";
+        if (len(map) != 1) throw \"1 and 1.0 are different keys\";
+        if (map[1] != \"float\") throw \"the value was not replaced\";
+        if ({0: \"zero\"}[-0.0] != \"zero\") throw \"0 and -0.0 are different keys\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn maps_keep_the_insertion_order() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var map = {\"b\": 1, \"a\": 2};
+        map[\"c\"] = 3;
+        map[\"b\"] = 4;
+        var order = keys(map);
+        if (order[0] != \"b\" or order[1] != \"a\" or order[2] != \"c\") throw \"wrong order\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn missing_and_invalid_keys_are_errors() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var map = {};
+        var errors = [];
+        try { map[\"x\"]; } catch (error) { push(errors, error.message); }
+        try { map[[]] = 1; } catch (error) { push(errors, error.message); }
+
+        if (errors[0] != \"Undefined key \\\"x\\\"\") throw errors[0];
+        if (errors[1] != \"[] can't be used as a map key\") throw errors[1];",
+    );
+
+    assert!(result.is_ok());
+}
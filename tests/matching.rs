@@ -0,0 +1,96 @@
+mod common;
+
+use rlox::{Config, Lox};
+
+#[test]
+fn missing_variants_are_reported_at_compile_time() {
+    let output = common::run(
+        "enum Color { Red, Green, Blue }
+        print \"ran\";
+        match (Color.Red) {
+            Color.Red => print \"red\";
+            Color.Green => print \"green\";
+        }",
+    );
+
+    assert_eq!(
+        output,
+        "[Line 3] Error: Match on Color doesn't cover Blue.\n"
+    );
+}
+
+#[test]
+fn every_arm_is_checked_and_guarded_arms_cover_nothing() {
+    let output = common::run(
+        "enum Color { Red, Green, Blue }
+        match (Color.Red) {
+            other if other == Color.Blue => print \"blue\";
+            Color.Red => print \"red\";
+            Color.Green if true => print \"green\";
+        }",
+    );
+
+    assert_eq!(
+        output,
+        "[Line 2] Error: Match on Color doesn't cover Green, Blue.\n"
+    );
+}
+
+#[test]
+fn wildcards_make_a_match_exhaustive() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "enum Color { Red, Green, Blue }
+        var name;
+        match (Color.Blue) {
+            Color.Red => name = \"red\";
+            _ => name = \"other\";
+        }
+        if (name != \"other\") throw \"wrong arm\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn failing_guards_fall_through_to_the_next_arm() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "enum Shape { Circle(radius), Square(side) }
+        fun describe(shape) {
+            match (shape) {
+                Shape.Circle(radius) if radius > 5 => return \"big circle\";
+                Shape.Circle(_) => return \"small circle\";
+                Shape.Square(_) => return \"square\";
+            }
+        }
+        if (describe(Shape.Circle(10)) != \"big circle\") throw \"wrong big circle\";
+        if (describe(Shape.Circle(1)) != \"small circle\") throw \"guard did not fall through\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn list_patterns_bind_the_rest() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun sum(list) {
+            match (list) {
+                [] => return 0;
+                [first, ...rest] => return first + sum(rest);
+            }
+        }
+        if (sum([1, 2, 3]) != 6) throw \"wrong sum\";
+
+        match ([1]) {
+            [a, b, ...rest] => throw \"matched too short a list\";
+            [a, ...rest] => if (len(rest) != 0) throw \"rest is not empty\";
+        }",
+    );
+
+    assert!(result.is_ok());
+}
@@ -0,0 +1,54 @@
+use rlox::{Config, Lox};
+
+const LIMIT: usize = 64 * 1024;
+
+fn limited() -> Lox {
+    Lox::new(Config {
+        memory_limit: Some(LIMIT),
+        ..Config::default()
+    })
+}
+
+#[test]
+fn growing_a_string_hits_the_limit() {
+    let lox = limited();
+
+    let result = lox.run(
+        "try {
+            var text = \"x\";
+            while (true) text = text + text;
+        } catch (error) {
+            if (error.message != \"Memory limit of 65536 bytes exceeded\") throw \"wrong error\";
+        }",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn growing_a_list_hits_the_limit() {
+    let lox = limited();
+
+    assert!(lox
+        .run("var list = []; while (true) push(list, 1);")
+        .is_err());
+    assert!(lox.heap_stats().allocated <= LIMIT);
+}
+
+#[test]
+fn collected_bytes_can_be_allocated_again() {
+    let lox = limited();
+
+    // Each round allocates a few kilobytes which are garbage once it ends,
+    // far more than the limit in total.
+    let result = lox.run(
+        "for (var round = 0; round < 1000; round += 1) {
+            var chunk = [\"x\"];
+            for (var i = 0; i < 10; i += 1) chunk[0] = chunk[0] + chunk[0];
+            push(chunk, chunk);
+        }",
+    );
+
+    assert!(result.is_ok());
+    assert!(lox.heap_stats().collections > 0);
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use std::fs;
+
+use common::Scripts;
+
+#[test]
+fn modules_are_found_relative_to_the_importing_file() {
+    let scripts = Scripts::new();
+    scripts
+        .write("lib/base.lox", "var x = 1;\nfun get() { return x; }")
+        .write(
+            "lib/derived.lox",
+            "import \"base.lox\" as base;\nvar y = base.x + 1;",
+        )
+        .write(
+            "main.lox",
+            "import \"lib/derived.lox\" as derived;
+            from \"lib/base.lox\" import x, get;
+            print derived.y;
+            print x;
+            print get();",
+        );
+
+    assert_eq!(scripts.run("main.lox"), "2\n1\n1\n");
+}
+
+#[test]
+fn modules_run_once_however_often_they_are_imported() {
+    let scripts = Scripts::new();
+    scripts
+        .write(
+            "counter.lox",
+            "print \"loading\";
+            var count = 0;
+            fun increment() { count = count + 1; return count; }",
+        )
+        .write(
+            "main.lox",
+            "import \"counter.lox\" as first;
+            import \"./counter.lox\" as second;
+            first.increment();
+            print second.increment();",
+        );
+
+    assert_eq!(scripts.run("main.lox"), "\"loading\"\n2\n");
+}
+
+#[test]
+fn import_cycles_are_reported() {
+    let scripts = Scripts::new();
+    scripts
+        .write("a.lox", "import \"b.lox\" as b;")
+        .write("b.lox", "import \"a.lox\" as a;")
+        .write("main.lox", "import \"a.lox\" as a;");
+    let a = fs::canonicalize(scripts.path("a.lox")).unwrap();
+    let b = fs::canonicalize(scripts.path("b.lox")).unwrap();
+
+    assert_eq!(
+        scripts.run("main.lox"),
+        format!(
+            "[Line 1] Runtime error: Import cycle detected: {} -> {} -> {}\n",
+            a.display(),
+            b.display(),
+            a.display()
+        )
+    );
+}
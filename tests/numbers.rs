@@ -0,0 +1,69 @@
+mod common;
+
+use rlox::{Config, Lox};
+
+#[test]
+fn division_keeps_the_fraction_of_integers() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "if (1 / 3 != 1.0 / 3.0) throw \"1 / 3 is truncated\";
+        if (7 / 2 != 3.5) throw \"7 / 2 is truncated\";
+        if (6 / 3 != 2) throw \"6 / 3 is not 2\";
+        var x = 7;
+        x /= 2;
+        if (x != 3.5) throw \"/= truncates\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn floor_division_gives_integers() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "if (7 ~/ 2 != 3) throw \"7 ~/ 2 is not 3\";
+        if (-7 ~/ 2 != -4) throw \"-7 ~/ 2 is not -4\";
+        if (7.5 ~/ 2 != 3) throw \"7.5 ~/ 2 is not 3\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn integer_division_by_zero_fails() {
+    let lox = Lox::new(Config::default());
+
+    assert!(lox.run("print 1 ~/ 0;").is_err());
+    assert!(lox.run("print 1 % 0;").is_err());
+}
+
+#[test]
+fn number_literals_have_prefixes_exponents_and_underscores() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "if (0xFF != 255 or 0b101 != 5) throw \"wrong prefixed integers\";
+        if (1_000_000 != 1000000) throw \"wrong separated integer\";
+        if (1e3 != 1000.0 or 2.5e-1 != 0.25) throw \"wrong exponents\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn malformed_number_literals_are_rejected() {
+    let output = common::run(
+        "print 0x;
+        print 1e;
+        print 1__0;",
+    );
+
+    assert_eq!(
+        output,
+        "[Line 1] Error: 0x is not a valid number, digits are missing after the prefix\n\
+        [Line 2] Error: 1e is not a valid number, digits are missing in the exponent\n\
+        [Line 3] Error: 1__0 is not a valid number, underscores must be between digits\n"
+    );
+}
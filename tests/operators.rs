@@ -0,0 +1,98 @@
+use rlox::{Config, Lox};
+
+// Each check throws when the operators group the wrong way, which fails the
+// run.
+
+#[test]
+fn binary_operators_are_left_associative() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "if (1 - 2 - 3 != -4) throw \"minus groups to the right\";
+        if (12 / 3 / 2 != 2) throw \"division groups to the right\";
+        if (7 % 4 % 2 != 1) throw \"modulo groups to the right\";
+        if (7 ~/ 2 ~/ 2 != 1) throw \"floor division groups to the right\";
+        if (64 >> 2 >> 1 != 8) throw \"shifts group to the right\";
+        if (1 + 2 * 3 - 4 != 3) throw \"wrong precedence\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn power_is_right_associative() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "if (2 ** 3 ** 2 != 512) throw \"power groups to the left\";
+        if (-2 ** 2 != -4) throw \"unary minus binds tighter than power\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn modulo_and_bitwise_operators() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "if (-7 % 3 != 2 or 7 % -3 != -2) throw \"modulo doesn't take the sign of the divisor\";
+        if (5.5 % 2 != 1.5) throw \"wrong float modulo\";
+        if ((6 & 3) != 2 or (6 | 3) != 7 or (6 ^ 3) != 5 or ~6 != -7) throw \"wrong bitwise\";
+        if (1 << 4 != 16 or -16 >> 2 != -4) throw \"wrong shifts\";
+        if (2 ** -1 != 0.5) throw \"wrong negative power\";
+
+        var errors = [];
+        try { 1 << 64; } catch (error) { push(errors, error.message); }
+        try { 1.5 & 1; } catch (error) { push(errors, error.message); }
+        try { 2 ** 64; } catch (error) { push(errors, error.message); }
+        if (errors[0] != \"Shift amount 64 is not between 0 and 63\") throw errors[0];
+        if (errors[1] != \"Operands of & must be integers, got 1.5 and 1\") throw errors[1];
+        if (errors[2] != \"Integer overflow in 2 ** 64\") throw errors[2];",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn increments_are_only_read_as_steps_at_the_end_of_a_statement() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var a = 5;
+        var b = 2;
+        if (a - -b != 7) throw \"a - -b is not a minus minus b\";
+        if (a--b != 7) throw \"a--b is not a minus minus b\";
+        if (--a != 5) throw \"--a is not a\";
+
+        var total = 0;
+        for (var i = 0; i < 3; i++) total += i;
+        if (total != 3) throw \"i++ is not a step\";
+        total--;
+        if (total != 2) throw \"total-- is not a step\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn compound_assignments_and_conditionals() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var x = 10;
+        x -= 4;
+        x *= 2;
+        x /= 4;
+        if (x != 3) throw \"wrong compound assignments\";
+
+        var list = [\"a\"];
+        list[0] += \"b\";
+        if (list[0] != \"ab\") throw \"wrong compound index assignment\";
+
+        if ((x > 2 ? \"big\" : \"small\") != \"big\") throw \"wrong conditional\";
+        if ((true ? 1 : false ? 2 : 3) != 1) throw \"conditionals group to the left\";",
+    );
+
+    assert!(result.is_ok());
+}
@@ -0,0 +1,23 @@
+use rlox::{Config, Lox};
+
+#[test]
+fn returning_from_top_level_code_is_a_compile_error() {
+    let lox = Lox::new(Config::default());
+
+    // A script that doesn't compile runs none of its statements, so `ran`
+    // stays undefined.
+    assert!(lox.run("var ran = true; return 1;").is_err());
+    assert!(lox.run("if (ran) print ran;").is_err());
+}
+
+#[test]
+fn functions_still_return() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun first(list) { for (var item in list) return item; }
+        if (first([1, 2]) != 1) throw \"wrong item\";",
+    );
+
+    assert!(result.is_ok());
+}
@@ -0,0 +1,28 @@
+mod common;
+
+#[test]
+fn a_byte_order_mark_and_windows_line_endings_keep_the_lines() {
+    let output = common::run("\u{feff}var café = 1;\r\nprint café;\r\n\r\nprint missing;\r\n");
+
+    assert_eq!(
+        output,
+        "1\n[Line 4] Runtime error: Undefined variable missing\n"
+    );
+}
+
+#[test]
+fn old_mac_line_endings_count_as_lines() {
+    let output = common::run("var x = 1;\rprint x;\r\rprint missing;");
+
+    assert_eq!(
+        output,
+        "1\n[Line 4] Runtime error: Undefined variable missing\n"
+    );
+}
+
+#[test]
+fn identifiers_follow_unicode_rules() {
+    let output = common::run("var 名前 = \"ok\";\nprint 名前;\nvar € = 1;");
+
+    assert_eq!(output, "[Line 3] Error: Unexpected character €\n");
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use rlox::{Config, Lox};
+
+#[test]
+fn invalid_escapes_are_reported() {
+    let output = common::run(
+        "print \"\\u{110000}\";
+        print \"\\q\";
+        print \"\\u{}\";",
+    );
+
+    assert_eq!(
+        output,
+        "[Line 1] Error: Invalid unicode escape \\u{110000}\n\
+        [Line 2] Error: Invalid escape sequence \\q\n\
+        [Line 3] Error: Invalid unicode escape \\u{}\n"
+    );
+}
+
+#[test]
+fn errors_after_an_invalid_escape_quote_their_own_text() {
+    let output = common::run("print \"\\q\"\"abc");
+
+    assert_eq!(
+        output,
+        "[Line 1] Error: Invalid escape sequence \\q\n\
+        [Line 1] Error: Unterminated string \"abc\n"
+    );
+}
+
+#[test]
+fn escapes_are_decoded_except_in_raw_strings() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "if (len(\"\\n\") != 1) throw \"\\\\n is not decoded\";
+        if (len(r\"\\n\") != 2) throw \"\\\\n is decoded in a raw string\";
+        if (\"\\u{48}\\u{1F600}\" != \"H😀\") throw \"wrong unicode escapes\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn interpolations_nest_strings_and_maps() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "var name = \"world\";
+        if (\"hello ${name}!\" != \"hello world!\") throw \"wrong interpolation\";
+        if (\"${\"inner ${name}\"}\" != \"inner world\") throw \"wrong nested string\";
+        if (\"${{\"a\": 1}[\"a\"] + 1}\" != \"2\") throw \"wrong map literal\";
+        if (\"${[1, 2]} \\${name}\" != \"[1, 2] $\" + \"{name}\") throw \"wrong escaped interpolation\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn unterminated_interpolations_are_reported() {
+    let output = common::run("print \"${1 + 2");
+
+    assert_eq!(
+        output,
+        "[Line 1] Error: Unterminated interpolation in string\n"
+    );
+}
@@ -0,0 +1,34 @@
+use rlox::{Config, Lox};
+
+// Without tail calls, each Lox call takes several native frames, and the
+// test thread's stack overflows after a few thousand of them.
+
+#[test]
+fn self_recursion_runs_in_constant_stack() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun count(n, total) {
+            if (n == 0) return total;
+            return count(n - 1, total + 1);
+        }
+
+        if (count(1000000, 0) != 1000000) throw \"wrong count\";",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn mutual_recursion_runs_in_constant_stack() {
+    let lox = Lox::new(Config::default());
+
+    let result = lox.run(
+        "fun isEven(n) { if (n == 0) return true; return isOdd(n - 1); }
+        fun isOdd(n) { if (n == 0) return false; return isEven(n - 1); }
+
+        if (!isEven(100000)) throw \"wrong parity\";",
+    );
+
+    assert!(result.is_ok());
+}